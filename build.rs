@@ -0,0 +1,30 @@
+// Find the newest migration, so the server can tell when it's pointed at a database that a newer
+// version of itself has already migrated
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+
+    // Versions are compared as numbers, since they aren't all the same length
+    let latest: u64 = fs::read_dir("migrations")
+        .expect("Could not read the migrations directory")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("up.sql").is_file())
+        .filter_map(|entry| {
+            // Diesel takes the version from everything before the first '_', minus any dashes
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let version = name.split('_').next().map(|v| v.replace('-', ""))?;
+            match version.parse() {
+                Ok(version) => Some(version),
+                Err(_) => panic!("Migration {} doesn't start with a numeric version", name),
+            }
+        })
+        .max()
+        .unwrap_or(0);
+
+    let out = Path::new(&env::var("OUT_DIR").expect("Cargo sets OUT_DIR")).join("schema.rs");
+    fs::write(&out, format!("const SCHEMA_VERSION: u64 = {};\n", latest))
+        .expect("Could not write the schema version");
+}
@@ -1,6 +1 @@
-REVOKE ALL ON DATABASE redmuddb FROM redmud CASCADE;
-REVOKE ALL ON ALL TABLES IN SCHEMA public FROM redmud CASCADE;
-
-DROP ROLE IF EXISTS redmud;
-DROP TABLE IF ExISTS accounts;
-
+DROP TABLE IF EXISTS accounts;
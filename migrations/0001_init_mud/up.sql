@@ -1,7 +1,3 @@
-CREATE ROLE redmud LOGIN PASSWORD 'redmud';
-
-GRANT CONNECT ON DATABASE redmuddb TO redmud;
-
 CREATE TABLE accounts (
   name TEXT NOT NULL PRIMARY KEY,
   email TEXT,
@@ -12,8 +8,6 @@ CREATE TABLE accounts (
   lastseen TIMESTAMP NOT NULL
 );
 
-GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO redmud;
-
 --CREATE TABLE map (
 --  id SERIAL NOT NULL PRIMARY KEY,
 --  spawn BOOLEAN NOT NULL DEFAULT false,
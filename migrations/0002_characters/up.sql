@@ -0,0 +1,8 @@
+CREATE TABLE characters (
+  name TEXT NOT NULL PRIMARY KEY,
+  account TEXT NOT NULL REFERENCES accounts (name) ON DELETE CASCADE,
+  created TIMESTAMP NOT NULL DEFAULT NOW(),
+  lastplayed TIMESTAMP NOT NULL
+);
+
+CREATE INDEX characters_account_idx ON characters (account);
@@ -0,0 +1,12 @@
+DROP TABLE IF EXISTS character_drafts;
+
+ALTER TABLE characters
+  DROP COLUMN description,
+  DROP COLUMN race,
+  DROP COLUMN class,
+  DROP COLUMN strength,
+  DROP COLUMN dexterity,
+  DROP COLUMN constitution,
+  DROP COLUMN intelligence,
+  DROP COLUMN wisdom,
+  DROP COLUMN charisma;
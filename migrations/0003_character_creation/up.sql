@@ -0,0 +1,26 @@
+ALTER TABLE characters
+  ADD COLUMN description TEXT NOT NULL DEFAULT '',
+  ADD COLUMN race TEXT NOT NULL DEFAULT 'human',
+  ADD COLUMN class TEXT NOT NULL DEFAULT 'warrior',
+  ADD COLUMN strength INTEGER NOT NULL DEFAULT 8,
+  ADD COLUMN dexterity INTEGER NOT NULL DEFAULT 8,
+  ADD COLUMN constitution INTEGER NOT NULL DEFAULT 8,
+  ADD COLUMN intelligence INTEGER NOT NULL DEFAULT 8,
+  ADD COLUMN wisdom INTEGER NOT NULL DEFAULT 8,
+  ADD COLUMN charisma INTEGER NOT NULL DEFAULT 8;
+
+-- Characters that are still being created. Each account may have one in progress.
+CREATE TABLE character_drafts (
+  account TEXT NOT NULL PRIMARY KEY REFERENCES accounts (name) ON DELETE CASCADE,
+  step INTEGER NOT NULL DEFAULT 0,
+  name TEXT,
+  description TEXT,
+  race TEXT,
+  class TEXT,
+  strength INTEGER NOT NULL DEFAULT 8,
+  dexterity INTEGER NOT NULL DEFAULT 8,
+  constitution INTEGER NOT NULL DEFAULT 8,
+  intelligence INTEGER NOT NULL DEFAULT 8,
+  wisdom INTEGER NOT NULL DEFAULT 8,
+  charisma INTEGER NOT NULL DEFAULT 8
+);
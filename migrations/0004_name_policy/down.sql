@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS characters_name_lower_idx;
+DROP INDEX IF EXISTS accounts_name_lower_idx;
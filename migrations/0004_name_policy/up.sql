@@ -0,0 +1,3 @@
+-- Names are unique regardless of case
+CREATE UNIQUE INDEX accounts_name_lower_idx ON accounts (lower(name));
+CREATE UNIQUE INDEX characters_name_lower_idx ON characters (lower(name));
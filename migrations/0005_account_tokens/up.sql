@@ -0,0 +1,10 @@
+-- One-time codes for verifying email addresses and resetting passwords
+CREATE TABLE account_tokens (
+  account TEXT NOT NULL REFERENCES accounts (name) ON DELETE CASCADE,
+  purpose TEXT NOT NULL,
+  token TEXT NOT NULL,
+  expires TIMESTAMP NOT NULL,
+  PRIMARY KEY (account, purpose)
+);
+
+UPDATE accounts SET email = NULL WHERE email = '';
@@ -0,0 +1,8 @@
+-- Accounts that have already been upgraded cannot be downgraded, and will need their passwords
+-- reset.
+DELETE FROM accounts WHERE salt IS NULL OR hash IS NULL;
+
+ALTER TABLE accounts
+  DROP COLUMN password,
+  ALTER COLUMN salt SET NOT NULL,
+  ALTER COLUMN hash SET NOT NULL;
@@ -0,0 +1,6 @@
+-- Passwords are stored as PHC strings, which carry their own algorithm, parameters and salt.
+-- The raw salt and hash are kept until each account is upgraded at its next login.
+ALTER TABLE accounts
+  ADD COLUMN password TEXT,
+  ALTER COLUMN salt DROP NOT NULL,
+  ALTER COLUMN hash DROP NOT NULL;
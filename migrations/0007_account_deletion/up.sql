@@ -0,0 +1,3 @@
+-- When the player asked for their account to be deleted. The account is purged once the grace
+-- period has passed, unless they log in again before then.
+ALTER TABLE accounts ADD COLUMN deleted TIMESTAMP;
@@ -0,0 +1,7 @@
+DROP TABLE IF EXISTS recovery_codes;
+
+ALTER TABLE accounts
+  DROP COLUMN role,
+  DROP COLUMN totp_secret,
+  DROP COLUMN totp_enabled,
+  DROP COLUMN totp_step;
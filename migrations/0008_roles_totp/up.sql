@@ -0,0 +1,17 @@
+ALTER TABLE accounts
+  ADD COLUMN role TEXT NOT NULL DEFAULT 'player',
+  -- Base32 TOTP secret. Set during enrollment, but only required at login once enabled.
+  ADD COLUMN totp_secret TEXT,
+  ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT false,
+  -- Time step of the last code accepted, so that no code can be used twice
+  ADD COLUMN totp_step BIGINT;
+
+-- One-time codes that stand in for a TOTP code when the player's authenticator is unavailable.
+-- Only hashes of the codes are stored.
+CREATE TABLE recovery_codes (
+  id SERIAL NOT NULL PRIMARY KEY,
+  account TEXT NOT NULL REFERENCES accounts (name) ON DELETE CASCADE,
+  hash TEXT NOT NULL
+);
+
+CREATE INDEX recovery_codes_account_idx ON recovery_codes (account);
@@ -0,0 +1,13 @@
+-- Bans on accounts, or on addresses given as an exact IP or a CIDR range
+CREATE TABLE bans (
+  id SERIAL NOT NULL PRIMARY KEY,
+  account TEXT REFERENCES accounts (name) ON DELETE CASCADE,
+  address TEXT,
+  reason TEXT NOT NULL,
+  issuer TEXT NOT NULL,
+  created TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires TIMESTAMP,
+  CHECK (account IS NOT NULL OR address IS NOT NULL)
+);
+
+CREATE INDEX bans_account_idx ON bans (account);
@@ -0,0 +1,3 @@
+ALTER TABLE characters DROP COLUMN IF EXISTS room;
+DROP TABLE IF EXISTS exits;
+DROP TABLE IF EXISTS rooms;
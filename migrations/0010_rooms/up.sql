@@ -0,0 +1,38 @@
+-- The world map: rooms, and the exits leading out of each of them by direction
+CREATE TABLE rooms (
+  id SERIAL NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL,
+  description TEXT NOT NULL DEFAULT '',
+  spawn BOOLEAN NOT NULL DEFAULT false,
+  safe BOOLEAN NOT NULL DEFAULT true
+);
+
+CREATE TABLE exits (
+  room INTEGER NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+  direction TEXT NOT NULL,
+  destination INTEGER NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+  PRIMARY KEY (room, direction)
+);
+
+-- Characters return to the room they were last in, or to a spawn room if it's gone
+ALTER TABLE characters ADD COLUMN room INTEGER REFERENCES rooms (id) ON DELETE SET NULL;
+
+-- A small starting area, so a new server has somewhere to put its players
+INSERT INTO rooms (id, name, description, spawn, safe) VALUES
+  (1, 'Town Square', 'Cobbled streets meet around a dry fountain. The town gate lies to the north, '
+    || 'the market to the east and the temple to the south.', true, true),
+  (2, 'North Gate', 'A squat stone gatehouse guards the road out of town.', false, true),
+  (3, 'Old Forest Road', 'The road narrows as the trees close in overhead. Something moves in the '
+    || 'undergrowth.', false, false),
+  (4, 'Market Street', 'Stalls line both sides of the street. A shop door stands open.', false, true),
+  (5, 'General Store', 'Shelves of rope, lamps and dried food crowd the little shop.', false, true),
+  (6, 'Temple Steps', 'Wide marble steps climb towards the temple doors.', false, true);
+SELECT setval('rooms_id_seq', (SELECT max(id) FROM rooms));
+
+INSERT INTO exits (room, direction, destination) VALUES
+  (1, 'north', 2), (1, 'east', 4), (1, 'south', 6),
+  (2, 'south', 1), (2, 'north', 3),
+  (3, 'south', 2),
+  (4, 'west', 1), (4, 'in', 5),
+  (5, 'out', 4),
+  (6, 'north', 1);
@@ -0,0 +1 @@
+ALTER TABLE exits DROP COLUMN IF EXISTS closed;
@@ -0,0 +1,2 @@
+-- Exits can be closed, like a barred gate, so that nobody can pass through them
+ALTER TABLE exits ADD COLUMN closed BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+ALTER TABLE characters DROP COLUMN IF EXISTS relative;
+ALTER TABLE characters DROP COLUMN IF EXISTS heading;
@@ -0,0 +1,3 @@
+-- Characters face a compass direction, which relative movement and relative views turn from
+ALTER TABLE characters ADD COLUMN heading TEXT NOT NULL DEFAULT 'north';
+ALTER TABLE characters ADD COLUMN relative BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,7 @@
+-- Commands players define for their characters, each standing for one or more other commands
+CREATE TABLE aliases (
+  character_name TEXT NOT NULL REFERENCES characters (name) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  expansion TEXT NOT NULL,
+  PRIMARY KEY (character_name, name)
+);
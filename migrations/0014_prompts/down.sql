@@ -0,0 +1,3 @@
+ALTER TABLE characters DROP COLUMN IF EXISTS prompt;
+ALTER TABLE characters DROP COLUMN IF EXISTS mp;
+ALTER TABLE characters DROP COLUMN IF EXISTS hp;
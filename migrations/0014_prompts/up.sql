@@ -0,0 +1,8 @@
+-- Hit points and mana, shown in the prompt. Characters start out whole, with the maximums
+-- Character::max_hp and Character::max_mp work out from their attributes.
+ALTER TABLE characters ADD COLUMN hp INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE characters ADD COLUMN mp INTEGER NOT NULL DEFAULT 0;
+UPDATE characters SET hp = 10 + 2 * constitution, mp = intelligence + wisdom;
+
+-- The prompt shown after each command, with tokens such as %h standing for the character's state
+ALTER TABLE characters ADD COLUMN prompt TEXT NOT NULL DEFAULT '<%h/%Hhp %m/%Mmp> ';
@@ -0,0 +1,3 @@
+-- Lines of output the player wants on each page. Null leaves it to the size their client
+-- reports, and 0 turns paging off.
+ALTER TABLE accounts ADD COLUMN page_length INTEGER;
@@ -0,0 +1,8 @@
+-- Chat channels each account has chosen to join or leave. Channels they have never chosen about
+-- keep the default given in the configuration file.
+CREATE TABLE channel_subscriptions (
+  account TEXT NOT NULL REFERENCES accounts (name) ON DELETE CASCADE,
+  channel TEXT NOT NULL,
+  subscribed BOOLEAN NOT NULL,
+  PRIMARY KEY (account, channel)
+);
@@ -0,0 +1,4 @@
+-- The start of each recovery code, kept in the clear so that a code given at login is checked
+-- against the one hash it could match rather than all of them. Codes handed out before this have
+-- none.
+ALTER TABLE recovery_codes ADD COLUMN prefix TEXT;
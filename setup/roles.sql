@@ -0,0 +1,15 @@
+-- Creates the role and database RedMUD runs as. Run this once as a PostgreSQL superuser, choosing
+-- a secure password and matching it in the [database] url of redmud.toml:
+--
+--     sudo -u postgres psql -f setup/roles.sql
+--
+-- The server applies its table migrations itself at startup, as this role. Since the role owns the
+-- database, it needs no other privileges to do so.
+CREATE ROLE redmud LOGIN PASSWORD 'redmud';
+CREATE DATABASE redmuddb OWNER redmud;
+
+-- Migrations grant nothing, since the tables they create belong to the role that runs them. A
+-- deployment that connects as a role other than the owner needs its own grants, such as:
+--
+--     GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO redmud_app;
+--     GRANT USAGE ON ALL SEQUENCES IN SCHEMA public TO redmud_app;
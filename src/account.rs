@@ -1,93 +1,707 @@
-use argon2rs::*;
-use diesel::insert_into;
-use diesel::prelude::*;
+use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
-use std::time::SystemTime;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
-use schema::accounts;
-use shared::*;
+use ban::{format_duration, Ban};
+use config::CONFIG;
+use error::{Error, Result};
+use mail::{Email, MAILER};
+use names;
+use password;
+use schema::{account_tokens, accounts};
+use shared::lock;
+use store::STORE;
+use totp;
+use world;
+
+// Purposes a one-time token may be issued for
+static VERIFY_EMAIL: &str = "verify";
+static RESET_PASSWORD: &str = "reset";
+
+// Number of recovery codes handed out when two-factor authentication is enabled
+const RECOVERY_CODES: usize = 10;
+// Length of the start of each recovery code that is stored in the clear, to find its hash by
+const RECOVERY_PREFIX: usize = 4;
+// Wrong second factors an account may be given in a row before it must wait to try again
+const MAX_FACTOR_FAILURES: u32 = 5;
+// How long, in seconds, an account must wait after too many wrong second factors
+const FACTOR_LOCKOUT: u64 = 15 * 60;
+
+lazy_static! {
+    // Wrong second factors given for each account lately, and when the last was given
+    static ref FACTOR_FAILURES: Mutex<HashMap<String, (u32, Instant)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// What an account is trusted to do, from least to most privileged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Player,
+    Builder,
+    Admin,
+}
+
+impl Role {
+    pub fn parse(name: &str) -> Option<Role> {
+        match name {
+            "player" => Some(Role::Player),
+            "builder" => Some(Role::Builder),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Role::Player => "player",
+            Role::Builder => "builder",
+            Role::Admin => "admin",
+        }
+    }
+}
 
 // TODO Other fields to associate with player accounts:
 // . failed login attempts since last login
-// . characters
-// . status (banned, player, god, admin, etc.)
-#[derive(Queryable, Insertable, Debug)]
+#[derive(Queryable, Insertable, Debug, Clone)]
 #[table_name = "accounts"]
 pub struct Account {
     pub name: String,
-    email: Option<String>,
-    valid: bool,
-    salt: Vec<u8>,
-    hash: Vec<u8>,
-    created: SystemTime,
-    lastseen: SystemTime,
+    pub(crate) email: Option<String>,
+    pub(crate) valid: bool,
+    pub(crate) salt: Option<Vec<u8>>, // Legacy Argon2i salt, cleared once the password is upgraded
+    pub(crate) hash: Option<Vec<u8>>, // Legacy Argon2i hash, cleared once the password is upgraded
+    pub(crate) created: SystemTime,
+    pub(crate) lastseen: SystemTime,
+    pub(crate) password: Option<String>, // PHC string of the password's Argon2id hash
+    pub(crate) deleted: Option<SystemTime>, // When the player asked for the account to be deleted
+    pub(crate) role: String,
+    pub(crate) totp_secret: Option<String>, // Base32 TOTP secret, pending until totp_enabled is set
+    pub(crate) totp_enabled: bool,
+    pub(crate) totp_step: Option<i64>, // Time step of the last TOTP code accepted
+    pub(crate) page_length: Option<i32>, // Lines per page of output, if the player has chosen
 }
 
 impl Account {
     /// Validate and register a new account and insert it into the database
-    pub fn new(name: String, passwd: String) -> Result<Account, String> {
-        use schema::accounts;
+    pub fn new(name: String, passwd: String) -> Result<Account> {
+        names::validate(&name)?;
 
-        let db_conn = SHARE.db_conn.get().unwrap();
-
-        // Check if the account already exists
-        if let Ok(_) = accounts::table.find(&name).first::<Account>(&*db_conn) {
-            return Err(format!(
+        // Check if the account already exists, in any combination of upper and lower case
+        let existing = STORE.find_account_nocase(&name).map_err(|e| {
+            error!("Database error checking for account {}: {}", name, e);
+            Error::Unavailable("Registration")
+        })?;
+        if existing.is_some() {
+            return Err(Error::Refused(format!(
                 "'{}' already exists. Please choose a different name.\n",
                 name
-            ));
+            )));
         } else {
-            // Generate a random, 32-byte salt
-            let mut salt = vec![0u8; 32];
-            thread_rng().fill(&mut salt[..]);
-
-            // Generate a hash from the password and salt
-            let mut hash = vec![0u8; 32];
-            let argon = Argon2::default(Variant::Argon2i);
-            argon.hash(&mut hash, passwd.as_bytes(), &salt, &[], &[]);
+            let phc = password::hash(&passwd).map_err(|e| {
+                error!("Could not hash password for {}: {}", name, e);
+                Error::Unavailable("Registration")
+            })?;
 
             let acct = Account {
                 name,
-                email: Some("".to_string()),
+                email: None,
                 valid: false,
-                salt,
-                hash,
+                salt: None,
+                hash: None,
                 created: SystemTime::now(),
                 lastseen: SystemTime::now(),
+                password: Some(phc),
+                deleted: None,
+                role: Role::Player.name().to_string(),
+                totp_secret: None,
+                totp_enabled: false,
+                totp_step: None,
+                page_length: None,
             };
 
             // Insert the account into the database
-            if let Ok(_) = insert_into(accounts::table)
-                .values(&acct)
-                .execute(&*db_conn)
-            {
+            if let Err(e) = STORE.insert_account(&acct) {
+                error!("Database error creating {:?}: {}", acct, e);
+                return Err(Error::Unavailable("Registration"));
+            } else {
                 info!("Registered new user: {}", acct.name);
                 return Ok(acct);
-            } else {
-                error!("Database error creating {:?}!", acct);
-                return Err(format!("Database error creating user!\n"));
             }
         }
     }
 
     // TODO Update lastseen field
     /// Validate a user's login credentials
-    pub fn login(name: String, passwd: String) -> Result<Account, String> {
-        use schema::accounts;
-
-        let db_conn = SHARE.db_conn.get().unwrap();
-
-        if let Ok(acct) = accounts::table.find(&name).first::<Account>(&*db_conn) {
-            let mut given_hash = vec![0u8; 32];
-            let argon = Argon2::default(Variant::Argon2i);
-            argon.hash(&mut given_hash, passwd.as_bytes(), &acct.salt, &[], &[]);
+    pub fn login(name: String, passwd: String) -> Result<Account> {
+        let found = STORE.find_account(&name).map_err(|e| {
+            error!("Database error looking up account {}: {}", name, e);
+            Error::Unavailable("Login")
+        })?;
+        if let Some(mut acct) = found {
+            // Accounts past their deletion grace period are as good as gone
+            if acct.deleted.map_or(false, |d| d < purge_cutoff()) {
+                return Err(Error::Refused(format!("Invalid login.\n")));
+            }
 
-            if given_hash == acct.hash {
+            if acct.check_password(&passwd) {
+                // Only tell someone they're banned once they've proven who they are
+                if let Some(ban) = Ban::find_account(&acct.name)? {
+                    info!("Refused login for banned account: {}", acct.name);
+                    return Err(Error::Refused(ban.notice()));
+                }
                 info!("Successful login for: {}", acct.name);
+                // Upgrade the stored hash if it predates the current algorithm or cost settings
+                if acct.password.as_ref().map_or(true, |phc| password::needs_rehash(phc)) {
+                    // The old hash still works, so a failure here can wait for the next login
+                    let _ = acct.set_password(&passwd);
+                }
                 return Ok(acct);
             }
         }
-        Err(format!("Invalid login.\n"))
+        Err(Error::Refused(format!("Invalid login.\n")))
+    }
+
+    /// Check a password against the account's stored hash
+    pub fn check_password(&self, passwd: &str) -> bool {
+        match (&self.password, &self.salt, &self.hash) {
+            (&Some(ref phc), _, _) => password::verify(passwd, phc),
+            (&None, &Some(ref salt), &Some(ref hash)) => {
+                password::verify_legacy(passwd, salt, hash)
+            }
+            _ => false,
+        }
+    }
+
+    /// Change the account's password, given the current one
+    pub fn change_password(&mut self, current: &str, new: &str) -> Result<()> {
+        if !self.check_password(current) {
+            return Err(Error::Refused(format!("Incorrect password.\n")));
+        }
+
+        self.set_password(new).map_err(|_| Error::Unavailable("Password changes"))?;
+        info!("Changed password for: {}", self.name);
+        Ok(())
+    }
+
+    // Hash and store a new password, replacing any legacy salt and hash. Failures are logged.
+    fn set_password(&mut self, passwd: &str) -> Result<()> {
+        let phc = password::hash(passwd).map_err(|e| {
+            error!("Could not hash password for {}: {}", self.name, e);
+            e
+        })?;
+        match STORE.set_password(&self.name, &phc) {
+            Ok(()) => {
+                self.password = Some(phc);
+                self.salt = None;
+                self.hash = None;
+                info!("Updated password hash for: {}", self.name);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Database error updating password for {}: {}", self.name, e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Lines per page of output the player has chosen. `None` leaves it to the size their client
+    /// reports, and `Some(0)` turns paging off.
+    pub fn page_length(&self) -> Option<usize> {
+        self.page_length.map(|n| n.max(0) as usize)
+    }
+
+    /// Choose how many lines of output make a page
+    pub fn set_page_length(&mut self, lines: Option<usize>) -> Result<()> {
+        let lines = lines.map(|n| n as i32);
+        STORE.set_page_length(&self.name, lines).map_err(|e| {
+            error!("Database error saving the page length of {}: {}", self.name, e);
+            Error::Unavailable("Paging")
+        })?;
+        self.page_length = lines;
+        Ok(())
+    }
+
+    /// What the account is trusted to do
+    pub fn role(&self) -> Role {
+        Role::parse(&self.role).unwrap_or(Role::Player)
+    }
+
+    /// Whether the player must give a second factor after their password to log in
+    pub fn has_second_factor(&self) -> bool {
+        self.totp_enabled
+    }
+
+    /// Whether the account's role requires two-factor authentication that it has not set up
+    pub fn missing_second_factor(&self) -> bool {
+        !self.totp_enabled && self.role_requires_totp()
+    }
+
+    // Whether the configuration makes two-factor authentication mandatory for this role
+    fn role_requires_totp(&self) -> bool {
+        CONFIG
+            .security
+            .require_totp
+            .iter()
+            .any(|r| Role::parse(r) == Some(self.role()))
+    }
+
+    /// Check a TOTP code, or one of the account's recovery codes, which is used up if it matches.
+    /// After too many wrong codes in a row, every code is refused for a while, with a message
+    /// saying how long is left.
+    pub fn check_second_factor(&mut self, code: &str) -> Result<bool> {
+        let lockout = Duration::from_secs(FACTOR_LOCKOUT);
+        match lock(&FACTOR_FAILURES).get(&self.name) {
+            Some(&(n, last)) if n >= MAX_FACTOR_FAILURES && last.elapsed() < lockout => {
+                warn!("Refused second factor for {}, who has given too many wrong", self.name);
+                return Err(Error::Refused(format!(
+                    "Too many failed codes; try again in {}.\n",
+                    format_duration(lockout - last.elapsed())
+                )));
+            }
+            _ => {}
+        }
+        let passed = self.try_second_factor(code);
+        let mut failures = lock(&FACTOR_FAILURES);
+        if passed {
+            failures.remove(&self.name);
+        } else {
+            let entry = failures.entry(self.name.clone()).or_insert((0, Instant::now()));
+            if entry.1.elapsed() >= lockout {
+                entry.0 = 0;
+            }
+            *entry = (entry.0 + 1, Instant::now());
+        }
+        Ok(passed)
+    }
+
+    // Check a second factor, without regard to how many have been wrong
+    fn try_second_factor(&mut self, code: &str) -> bool {
+        let step = self.totp_secret
+            .as_ref()
+            .and_then(|secret| totp::verify(secret, code, self.totp_step));
+        if let Some(step) = step {
+            if let Err(e) = STORE.set_totp_step(&self.name, step) {
+                error!("Database error recording TOTP use for {}: {}", self.name, e);
+                return false;
+            }
+            self.totp_step = Some(step);
+            return true;
+        }
+
+        // Hashing is slow by design, so only codes shaped like a recovery code are hashed, and
+        // then only against the one they could be
+        let code = code.to_lowercase();
+        let prefix = match recovery_code_shape(&code) {
+            Some(prefix) => prefix,
+            None => return false,
+        };
+        let codes = STORE.recovery_codes(&self.name, prefix).unwrap_or_default();
+        if let Some(&(id, _)) = codes.iter().find(|&&(_, ref hash)| password::verify(&code, hash)) {
+            if let Ok(true) = STORE.delete_recovery_code(id) {
+                info!("{} used a recovery code", self.name);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Start enrolling in two-factor authentication, returning the otpauth URI of a new secret.
+    /// The secret is not required at login until it has been confirmed with a code.
+    pub fn enroll_totp(&mut self, passwd: &str) -> Result<String> {
+        if !self.check_password(passwd) {
+            return Err(Error::Refused(format!("Incorrect password.\n")));
+        }
+        if self.totp_enabled {
+            return Err(Error::Refused(format!(
+                "Two-factor authentication is already enabled.\n"
+            )));
+        }
+
+        let secret = totp::generate_secret();
+        let uri = totp::uri(&self.name, &secret);
+        if let Err(e) = STORE.set_totp(&self.name, Some(&secret), false, None) {
+            error!("Database error enrolling {} in TOTP: {}", self.name, e);
+            return Err(Error::Unavailable("Two-factor authentication"));
+        }
+        self.totp_secret = Some(secret);
+        self.totp_step = None;
+        Ok(uri)
+    }
+
+    /// Confirm enrollment with a code from the player's authenticator, enabling two-factor
+    /// authentication and returning a fresh set of recovery codes
+    pub fn confirm_totp(&mut self, code: &str) -> Result<Vec<String>> {
+        if self.totp_enabled {
+            return Err(Error::Refused(format!(
+                "Two-factor authentication is already enabled.\n"
+            )));
+        }
+        let step = match self.totp_secret {
+            Some(ref secret) => totp::verify(secret, code, None),
+            None => return Err(Error::Refused(format!("Use '2fa enroll <password>' first.\n"))),
+        };
+        let step = match step {
+            Some(step) => step,
+            None => {
+                return Err(Error::Refused(format!(
+                    "That code is not valid. Check your device's clock.\n"
+                )))
+            }
+        };
+
+        let secret = self.totp_secret.as_ref().map(|s| s.as_str());
+        if let Err(e) = STORE.set_totp(&self.name, secret, true, Some(step)) {
+            error!("Database error enabling TOTP for {}: {}", self.name, e);
+            return Err(Error::Unavailable("Two-factor authentication"));
+        }
+        self.totp_enabled = true;
+        self.totp_step = Some(step);
+        info!("Enabled two-factor authentication for: {}", self.name);
+        self.new_recovery_codes()
+    }
+
+    /// Turn off two-factor authentication, given the password and a current code
+    pub fn disable_totp(&mut self, passwd: &str, code: &str) -> Result<()> {
+        if !self.totp_enabled {
+            return Err(Error::Refused(format!(
+                "Two-factor authentication is not enabled.\n"
+            )));
+        }
+        if !self.check_password(passwd) || !self.check_second_factor(code)? {
+            return Err(Error::Refused(format!("Incorrect password or code.\n")));
+        }
+        if self.role_requires_totp() {
+            return Err(Error::Refused(format!(
+                "Accounts with the {} role must use two-factor authentication.\n",
+                self.role().name()
+            )));
+        }
+
+        if let Err(e) = STORE.set_totp(&self.name, None, false, None) {
+            error!("Database error disabling TOTP for {}: {}", self.name, e);
+            return Err(Error::Unavailable("Two-factor authentication"));
+        }
+        self.totp_secret = None;
+        self.totp_enabled = false;
+        self.totp_step = None;
+        // Leftover codes would be harmless, since they're never asked for without TOTP enabled
+        if let Err(e) = STORE.replace_recovery_codes(&self.name, &[]) {
+            error!("Database error removing recovery codes for {}: {}", self.name, e);
+        }
+        info!("Disabled two-factor authentication for: {}", self.name);
+        Ok(())
+    }
+
+    /// Replace the account's recovery codes with a fresh set, given its password
+    pub fn regenerate_recovery_codes(&mut self, passwd: &str) -> Result<Vec<String>> {
+        if !self.totp_enabled {
+            return Err(Error::Refused(format!(
+                "Two-factor authentication is not enabled.\n"
+            )));
+        }
+        if !self.check_password(passwd) {
+            return Err(Error::Refused(format!("Incorrect password.\n")));
+        }
+        self.new_recovery_codes()
+    }
+
+    // Replace any existing recovery codes, storing only their prefixes and hashes
+    fn new_recovery_codes(&self) -> Result<Vec<String>> {
+        let codes: Vec<String> = (0..RECOVERY_CODES)
+            .map(|_| {
+                let raw: String = thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(RECOVERY_PREFIX + 10)
+                    .collect::<String>()
+                    .to_lowercase();
+                let (prefix, rest) = raw.split_at(RECOVERY_PREFIX);
+                format!("{}-{}-{}", prefix, &rest[..5], &rest[5..])
+            })
+            .collect();
+        let mut hashes = Vec::new();
+        for code in &codes {
+            match password::hash(code) {
+                Ok(hash) => hashes.push((code[..RECOVERY_PREFIX].to_string(), hash)),
+                Err(e) => {
+                    error!("Could not hash recovery code for {}: {}", self.name, e);
+                    return Err(Error::Unavailable("Recovery codes"));
+                }
+            }
+        }
+
+        match STORE.replace_recovery_codes(&self.name, &hashes) {
+            Ok(()) => Ok(codes),
+            Err(e) => {
+                error!("Database error storing recovery codes for {}: {}", self.name, e);
+                Err(Error::Unavailable("Recovery codes"))
+            }
+        }
+    }
+
+    /// The account's email address and whether it has been verified
+    pub fn email(&self) -> Option<(&str, bool)> {
+        self.email.as_ref().map(|e| (e.as_str(), self.valid))
+    }
+
+    /// Change the account's email address and send a verification code to it
+    pub fn set_email(&mut self, email: &str) -> Result<()> {
+        if !valid_email(email) {
+            return Err(Error::Refused(format!("'{}' is not a valid email address.\n", email)));
+        }
+
+        if let Err(e) = STORE.set_email(&self.name, Some(email), false) {
+            error!("Database error setting email for {}: {}", self.name, e);
+            return Err(Error::Unavailable("Email"));
+        }
+        self.email = Some(email.to_string());
+        self.valid = false;
+
+        let code = issue_token(&self.name, VERIFY_EMAIL)?;
+        let email = Email {
+            to: email.to_string(),
+            subject: format!("Verify your RedMUD email address"),
+            body: format!(
+                "Hello {},\n\n\
+                 Your RedMUD email verification code is: {}\n\n\
+                 Log in and enter 'verify {}' in the lobby to confirm this address.\n\
+                 The code expires in {} minutes.\n",
+                self.name, code, code, CONFIG.mail.token_minutes
+            ),
+        };
+        // Let the player know how it went once the mail thread has tried to send it
+        let (name, address) = (self.name.clone(), email.to.clone());
+        MAILER.post(email, move |sent| {
+            let msg = match sent {
+                Ok(()) => format!(
+                    "A verification code has been sent to {}. Enter 'verify <code>' to confirm \
+                     it.\n",
+                    address
+                ),
+                Err(e) => {
+                    error!("Could not send mail to {}: {}", address, e);
+                    format!(
+                        "The verification code could not be sent to {}. Please try again \
+                         later.\n",
+                        address
+                    )
+                }
+            };
+            world::tell_player(&name, &msg);
+        });
+        Ok(())
+    }
+
+    /// Confirm the account's email address using the code that was mailed to it
+    pub fn verify_email(&mut self, code: &str) -> Result<()> {
+        if self.email.is_none() {
+            return Err(Error::Refused(format!("You have not set an email address yet.\n")));
+        }
+        if !redeem_token(&self.name, VERIFY_EMAIL, code) {
+            return Err(Error::Refused(format!(
+                "That verification code is invalid or has expired.\n"
+            )));
+        }
+        if let Err(e) = STORE.set_email(&self.name, self.email.as_ref().map(|e| e.as_str()), true) {
+            error!("Database error verifying email for {}: {}", self.name, e);
+            return Err(Error::Unavailable("Email"));
+        }
+        self.valid = true;
+        info!("Verified email address for: {}", self.name);
+        Ok(())
+    }
+
+    /// Schedule the account for deletion once the grace period has passed, given its password
+    pub fn schedule_deletion(&mut self, passwd: &str) -> Result<()> {
+        if !self.check_password(passwd) {
+            return Err(Error::Refused(format!("Incorrect password.\n")));
+        }
+
+        let now = SystemTime::now();
+        if let Err(e) = STORE.set_deleted(&self.name, Some(now)) {
+            error!("Database error deleting account {}: {}", self.name, e);
+            return Err(Error::Unavailable("Account deletion"));
+        }
+        self.deleted = Some(now);
+        info!("Scheduled account for deletion: {}", self.name);
+        Ok(())
+    }
+
+    /// Cancel a pending deletion, returning whether there was one to cancel
+    pub fn cancel_deletion(&mut self) -> bool {
+        if self.deleted.is_none() {
+            return false;
+        }
+
+        if let Err(e) = STORE.set_deleted(&self.name, None) {
+            error!("Database error restoring account {}: {}", self.name, e);
+            return false;
+        }
+        self.deleted = None;
+        info!("Cancelled deletion of account: {}", self.name);
+        true
+    }
+
+    /// Permanently remove accounts whose deletion grace period has passed. Their characters,
+    /// unfinished drafts and any outstanding mailed codes go with them.
+    pub fn purge_deleted() {
+        match STORE.purge_accounts(purge_cutoff()) {
+            Ok(0) => {}
+            Ok(n) => info!("Purged {} deleted accounts", n),
+            Err(e) => error!("Database error purging deleted accounts: {}", e),
+        }
+    }
+
+    /// Mail a one-time password reset code to the account's verified email address. Failures are
+    /// only logged, so that players cannot use this to discover which accounts exist.
+    pub fn request_reset(name: &str) {
+        let acct = match STORE.find_account(name) {
+            Ok(Some(acct)) => acct,
+            _ => return,
+        };
+        let email = match acct.email {
+            Some(ref email) if acct.valid => email.clone(),
+            _ => {
+                info!("Password reset requested for {}, who has no verified email", name);
+                return;
+            }
+        };
+
+        let code = match issue_token(&acct.name, RESET_PASSWORD) {
+            Ok(code) => code,
+            Err(_) => return,
+        };
+        let email = Email {
+            to: email,
+            subject: format!("Reset your RedMUD password"),
+            body: format!(
+                "Hello {},\n\n\
+                 Someone asked to reset the password for your RedMUD account. If it was you,\n\
+                 enter 'forgot {} {} <new password>' at the login menu.\n\
+                 The code expires in {} minutes. If it wasn't you, you may ignore this mail.\n",
+                acct.name, acct.name, code, CONFIG.mail.token_minutes
+            ),
+        };
+        // Whether it was sent isn't for the player to know, or anyone could learn who has an
+        // account with a verified address
+        MAILER.post(email, move |sent| match sent {
+            Ok(()) => info!("Sent password reset code to: {}", acct.name),
+            Err(e) => error!("Could not send password reset code to {}: {}", acct.name, e),
+        });
+    }
+
+    /// Set a new password for the account using a code from `request_reset`
+    pub fn reset_password(name: &str, code: &str, passwd: &str) -> Result<()> {
+        let invalid = || Error::Refused(format!("That reset code is invalid or has expired.\n"));
+        let mut acct = match STORE.find_account(name) {
+            Ok(Some(acct)) => acct,
+            Ok(None) => return Err(invalid()),
+            Err(e) => {
+                error!("Database error looking up account {}: {}", name, e);
+                return Err(Error::Unavailable("Password reset"));
+            }
+        };
+        if !redeem_token(&acct.name, RESET_PASSWORD, code) {
+            return Err(invalid());
+        }
+
+        acct.set_password(passwd).map_err(|_| Error::Unavailable("Password reset"))?;
+        info!("Reset password for: {}", name);
+        Ok(())
+    }
+}
+
+/// A one-time code mailed to a player to prove they own their email address
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "account_tokens"]
+pub struct Token {
+    pub account: String,
+    pub purpose: String,
+    pub token: String,
+    pub expires: SystemTime,
+}
+
+// Accounts deleted before this time are past their grace period
+fn purge_cutoff() -> SystemTime {
+    SystemTime::now() - Duration::from_secs(CONFIG.security.deletion_grace_days * 24 * 60 * 60)
+}
+
+// Whether the code is shaped like a recovery code, as in "abcd-efghi-jklmn", giving its prefix.
+// Codes handed out before prefixes were stored are shaped like "efghi-jklmn", and have none.
+fn recovery_code_shape(code: &str) -> Option<Option<&str>> {
+    let groups: Vec<&str> = code.split('-').collect();
+    let alnum = |g: &&str| g.chars().all(|c| c.is_ascii_alphanumeric());
+    if !groups.iter().all(alnum) {
+        return None;
+    }
+    match groups.as_slice() {
+        [prefix, a, b] if prefix.len() == RECOVERY_PREFIX && a.len() == 5 && b.len() == 5 => {
+            Some(Some(prefix))
+        }
+        [a, b] if a.len() == 5 && b.len() == 5 => Some(None),
+        _ => None,
+    }
+}
+
+// A loose sanity check. The verification mail is what proves an address really works.
+fn valid_email(email: &str) -> bool {
+    let mut parts = email.splitn(2, '@');
+    match (parts.next(), parts.next()) {
+        (Some(local), Some(domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+                && email.len() <= 254
+                && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+        }
+        _ => false,
+    }
+}
+
+// Replace any outstanding token for this purpose with a fresh one and return its code
+fn issue_token(name: &str, purpose: &str) -> Result<String> {
+    let code = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .collect::<String>()
+        .to_uppercase();
+    let token = Token {
+        account: name.to_string(),
+        purpose: purpose.to_string(),
+        token: code.clone(),
+        expires: SystemTime::now() + Duration::from_secs(CONFIG.mail.token_minutes * 60),
+    };
+
+    match STORE.replace_token(&token) {
+        Ok(()) => Ok(code),
+        Err(e) => {
+            error!("Database error issuing {:?}: {}", token, e);
+            Err(Error::Unavailable("Email"))
+        }
+    }
+}
+
+// Check a code against the outstanding token for this purpose, using it up if it matches
+fn redeem_token(name: &str, purpose: &str, code: &str) -> bool {
+    match STORE.find_token(name, purpose) {
+        Ok(Some(ref token)) if token.expires < SystemTime::now() => {
+            let _ = STORE.delete_token(name, purpose);
+            false
+        }
+        // Codes are issued in upper case, but players may type them in either
+        Ok(Some(ref token))
+            if password::constant_time_eq(
+                token.token.to_uppercase().as_bytes(),
+                code.trim().to_uppercase().as_bytes(),
+            ) =>
+        {
+            let _ = STORE.delete_token(name, purpose);
+            true
+        }
+        _ => false,
     }
 }
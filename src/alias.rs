@@ -0,0 +1,222 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use config::CONFIG;
+use error::{Error, Result};
+use shared::lock;
+use store::STORE;
+
+// Commands that can't be aliased, so that a player can always undo what they've done
+static RESERVED: &[&str] = &["alias", "unalias"];
+
+lazy_static! {
+    // Aliases of characters in the world, keyed by character name. Loaded when first needed and
+    // dropped when the character leaves.
+    static ref ALIASES: Mutex<HashMap<String, BTreeMap<String, String>>> =
+        Mutex::new(HashMap::new());
+}
+
+// Work with the character's aliases, loading them if need be. The database is read without the
+// lock held, so one player's slow query doesn't hold up everyone else's aliases.
+fn with_aliases<T, F: FnOnce(&mut BTreeMap<String, String>) -> T>(chr: &str, f: F) -> Result<T> {
+    if let Some(aliases) = lock(&ALIASES).get_mut(chr) {
+        return Ok(f(aliases));
+    }
+    let loaded = STORE.aliases(chr).map_err(|err| {
+        error!("Database error loading the aliases of {}: {}", chr, err);
+        Error::Unavailable("Aliases")
+    })?;
+    let mut cache = lock(&ALIASES);
+    let aliases = cache
+        .entry(chr.to_string())
+        .or_insert_with(|| loaded.into_iter().collect());
+    Ok(f(aliases))
+}
+
+/// The character's aliases, in order of name
+pub fn list(chr: &str) -> Result<Vec<(String, String)>> {
+    with_aliases(chr, |a| {
+        a.iter()
+            .map(|(name, expansion)| (name.clone(), expansion.clone()))
+            .collect()
+    })
+}
+
+/// What one of the character's aliases expands into
+pub fn get(chr: &str, name: &str) -> Result<Option<String>> {
+    with_aliases(chr, |a| a.get(&name.to_lowercase()).cloned())
+}
+
+/// Define an alias for the character, or change what it expands into
+pub fn set(chr: &str, name: &str, expansion: &str) -> Result<()> {
+    let name = name.to_lowercase();
+    if RESERVED.contains(&&*name) {
+        return Err(Error::Refused(format!("'{}' can't be made an alias.\n", name)));
+    }
+    if name.contains(';') || name.contains('$') {
+        return Err(Error::Refused(format!(
+            "Alias names may not contain ';' or '$'.\n"
+        )));
+    }
+
+    with_aliases(chr, |a| {
+        if !a.contains_key(&name) && a.len() >= CONFIG.game.max_aliases {
+            return Err(Error::Refused(format!(
+                "You already have {} aliases, the most a character may have.\n",
+                a.len()
+            )));
+        }
+        Ok(())
+    })??;
+    STORE.set_alias(chr, &name, expansion).map_err(|e| {
+        error!("Database error saving alias {} of {}: {}", name, chr, e);
+        Error::Unavailable("Aliases")
+    })?;
+    with_aliases(chr, |a| {
+        a.insert(name, expansion.to_string());
+    })
+}
+
+/// Remove one of the character's aliases. Returns whether there was such an alias.
+pub fn remove(chr: &str, name: &str) -> Result<bool> {
+    let name = name.to_lowercase();
+    if !with_aliases(chr, |a| a.contains_key(&name))? {
+        return Ok(false);
+    }
+    STORE.delete_alias(chr, &name).map_err(|e| {
+        error!("Database error deleting alias {} of {}: {}", name, chr, e);
+        Error::Unavailable("Aliases")
+    })?;
+    with_aliases(chr, |a| a.remove(&name).is_some())
+}
+
+/// Drop the character's aliases from memory as they leave the world
+pub fn forget(chr: &str) {
+    lock(&ALIASES).remove(chr);
+}
+
+/// Expand any alias at the start of a line into the commands it stands for. Aliases may run
+/// several commands separated by ';', and use other aliases, though never themselves. Lines
+/// that don't start with an alias are returned as they are.
+pub fn expand(chr: &str, line: &str) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let expanded = with_aliases(chr, |a| expand_line(a, line, &mut Vec::new(), &mut lines));
+    match expanded {
+        Ok(Ok(())) => Ok(lines),
+        Ok(Err(e)) => Err(e),
+        // Without their aliases, the player can still enter commands in full
+        Err(_) => Ok(vec![line.to_string()]),
+    }
+}
+
+// Expand the line onto the end of `lines`, given the aliases already being expanded
+fn expand_line(
+    aliases: &BTreeMap<String, String>,
+    line: &str,
+    active: &mut Vec<String>,
+    lines: &mut Vec<String>,
+) -> Result<()> {
+    let line = line.trim();
+    let (word, rest) = match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], line[end..].trim()),
+        None => (line, ""),
+    };
+    let name = word.to_lowercase();
+
+    match aliases.get(&name) {
+        Some(expansion) if !active.contains(&name) => {
+            if active.len() >= CONFIG.game.alias_depth {
+                return Err(Error::Refused(format!(
+                    "Your aliases go more than {} deep, so '{}' was not run.\n",
+                    CONFIG.game.alias_depth, active[0]
+                )));
+            }
+            active.push(name);
+            for command in substitute(expansion, rest).split(';') {
+                expand_line(aliases, command, active, lines)?;
+            }
+            active.pop();
+        }
+        _ if line.is_empty() && !active.is_empty() => {} // As between ';;'
+        _ => {
+            if lines.len() >= CONFIG.game.alias_commands {
+                return Err(Error::Refused(format!(
+                    "Your aliases expand into more than {} commands, so none were run.\n",
+                    CONFIG.game.alias_commands
+                )));
+            }
+            lines.push(line.to_string());
+        }
+    }
+    Ok(())
+}
+
+// Put the arguments given to an alias in place of $1 to $9 and $*, with $$ for a dollar sign.
+// An expansion that doesn't mention its arguments has them added to the end.
+fn substitute(expansion: &str, rest: &str) -> String {
+    let args: Vec<&str> = rest.split_whitespace().collect();
+    let mut used = false;
+    let mut text = String::new();
+    let mut chars = expansion.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            text.push(c);
+            continue;
+        }
+        match chars.peek().cloned() {
+            Some('*') => {
+                text.push_str(rest);
+                used = true;
+            }
+            Some(d @ '1'..='9') => {
+                let n = d as usize - '1' as usize;
+                text.push_str(args.get(n).cloned().unwrap_or(""));
+                used = true;
+            }
+            Some('$') => text.push('$'),
+            _ => {
+                text.push('$');
+                continue;
+            }
+        }
+        chars.next();
+    }
+    if !used && !rest.is_empty() {
+        text.push(' ');
+        text.push_str(rest);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitute_places_arguments() {
+        assert_eq!(substitute("kill $1 with $2", "orc sword"), "kill orc with sword");
+        assert_eq!(substitute("say $*", "hello there"), "say hello there");
+        assert_eq!(substitute("give $3", "a b"), "give ");
+        assert_eq!(substitute("say $$5 for $1", "bread"), "say $5 for bread");
+        assert_eq!(substitute("say 5$", ""), "say 5$");
+    }
+
+    #[test]
+    fn substitute_adds_unused_arguments_at_the_end() {
+        assert_eq!(substitute("look", "north"), "look north");
+        assert_eq!(substitute("look", ""), "look");
+    }
+
+    #[test]
+    fn expand_line_runs_nested_aliases_but_not_recursive_ones() {
+        let aliases: BTreeMap<String, String> = vec![
+            ("k".to_string(), "kill $1;look".to_string()),
+            ("kk".to_string(), "k $1;k $1".to_string()),
+            ("look".to_string(), "look".to_string()),
+        ].into_iter()
+            .collect();
+        let mut lines = Vec::new();
+        expand_line(&aliases, "kk orc", &mut Vec::new(), &mut lines).unwrap();
+        assert_eq!(lines, vec!["kill orc", "look", "kill orc", "look"]);
+    }
+}
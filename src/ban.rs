@@ -0,0 +1,380 @@
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use error::{Error, Result};
+use schema::bans;
+use shared::lock;
+use store::STORE;
+
+lazy_static! {
+    // Bans on addresses, checked as each connection is accepted without waiting on the database.
+    // Reloaded whenever a ban is issued or lifted, and None if they couldn't be loaded.
+    static ref ADDRESS_BANS: Mutex<Option<Vec<Ban>>> = Mutex::new(None);
+}
+
+/// A ban on an account, or on an address or range of addresses
+#[derive(Queryable, Debug, Clone)]
+pub struct Ban {
+    pub id: i32,
+    pub account: Option<String>,
+    pub address: Option<String>, // An exact IP or a CIDR range
+    pub reason: String,
+    pub issuer: String,          // Name of the admin who issued the ban
+    pub created: SystemTime,
+    pub expires: Option<SystemTime>, // Bans without an expiry last until they are lifted
+}
+
+/// A ban about to be issued, before it has been given an id
+#[derive(Insertable, Debug)]
+#[table_name = "bans"]
+pub struct NewBan<'a> {
+    pub account: Option<&'a str>,
+    pub address: Option<String>,
+    pub reason: &'a str,
+    pub issuer: &'a str,
+    pub created: SystemTime,
+    pub expires: Option<SystemTime>,
+}
+
+impl Ban {
+    /// Ban an account, optionally for a limited time
+    pub fn account(
+        name: &str,
+        issuer: &str,
+        reason: &str,
+        duration: Option<Duration>,
+    ) -> Result<Ban> {
+        match STORE.find_account(name) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return Err(Error::Refused(format!(
+                    "There is no account named '{}'.\n",
+                    name
+                )))
+            }
+            Err(e) => {
+                error!("Database error looking up account {}: {}", name, e);
+                return Err(Error::Unavailable("Bans"));
+            }
+        }
+        Ban::insert(NewBan {
+            account: Some(name),
+            address: None,
+            reason,
+            issuer,
+            created: SystemTime::now(),
+            expires: expiry(duration)?,
+        })
+    }
+
+    /// Ban an IP address or CIDR range, optionally for a limited time
+    pub fn address(
+        range: &str,
+        issuer: &str,
+        reason: &str,
+        duration: Option<Duration>,
+    ) -> Result<Ban> {
+        let cidr = Cidr::parse(range).ok_or_else(|| {
+            Error::Refused(format!("'{}' is not an IP address or CIDR range.\n", range))
+        })?;
+        Ban::insert(NewBan {
+            account: None,
+            address: Some(cidr.to_string()),
+            reason,
+            issuer,
+            created: SystemTime::now(),
+            expires: expiry(duration)?,
+        })
+    }
+
+    fn insert(ban: NewBan) -> Result<Ban> {
+        match STORE.insert_ban(&ban) {
+            Ok(ban) => {
+                info!("{} issued {:?}", ban.issuer, ban);
+                load_address_bans();
+                Ok(ban)
+            }
+            Err(e) => {
+                error!("Database error issuing {:?}: {}", ban, e);
+                Err(Error::Unavailable("Bans"))
+            }
+        }
+    }
+
+    /// List every ban that is still in effect
+    pub fn list() -> Result<Vec<Ban>> {
+        STORE.active_bans(SystemTime::now()).map_err(|e| {
+            error!("Database error listing bans: {}", e);
+            Error::Unavailable("Bans")
+        })
+    }
+
+    /// Lift a ban before it expires
+    pub fn lift(id: i32) -> Result<()> {
+        match STORE.delete_ban(id) {
+            Ok(false) => Err(Error::Refused(format!("There is no ban #{}.\n", id))),
+            Ok(true) => {
+                info!("Lifted ban #{}", id);
+                load_address_bans();
+                Ok(())
+            }
+            Err(e) => {
+                error!("Database error lifting ban #{}: {}", id, e);
+                Err(Error::Unavailable("Bans"))
+            }
+        }
+    }
+
+    /// Find a ban in effect on the account, if there is one. Fails if the bans can't be read, so
+    /// that an outage doesn't let banned players in.
+    pub fn find_account(name: &str) -> Result<Option<Ban>> {
+        Ok(Ban::list()?
+            .into_iter()
+            .find(|b| b.account.as_ref().map_or(false, |a| a == name)))
+    }
+
+    /// Find a ban in effect on an address or a range containing it, if there is one. Looks only
+    /// at the bans already loaded, and fails if they couldn't be.
+    pub fn find_address(ip: IpAddr) -> Result<Option<Ban>> {
+        let now = SystemTime::now();
+        match *lock(&ADDRESS_BANS) {
+            Some(ref bans) => Ok(bans
+                .iter()
+                .filter(|b| b.expires.map_or(true, |e| e > now))
+                .find(|b| {
+                    b.address
+                        .as_ref()
+                        .and_then(|a| Cidr::parse(a))
+                        .map_or(false, |cidr| cidr.contains(ip))
+                })
+                .cloned()),
+            None => Err(Error::Unavailable("Bans")),
+        }
+    }
+
+    /// Explain the ban to the player it keeps out
+    pub fn notice(&self) -> String {
+        let until = match self.expires {
+            Some(expires) => match expires.duration_since(SystemTime::now()) {
+                Ok(left) => format!("The ban expires in {}.", format_duration(left)),
+                Err(_) => format!("The ban has expired."),
+            },
+            None => format!("The ban does not expire."),
+        };
+        format!("You have been banned: {}\n{}\n", self.reason, until)
+    }
+
+    /// Summarize the ban for admins
+    pub fn summary(&self) -> String {
+        let target = match (&self.account, &self.address) {
+            (&Some(ref account), _) => format!("account {}", account),
+            (_, &Some(ref address)) => format!("address {}", address),
+            _ => format!("nothing"),
+        };
+        let until = match self.expires.map(|e| e.duration_since(SystemTime::now())) {
+            Some(Ok(left)) => format!("{} left", format_duration(left)),
+            Some(Err(_)) => format!("expired"),
+            None => format!("permanent"),
+        };
+        format!(
+            "#{} {} by {} ({}): {}",
+            self.id, target, self.issuer, until, self.reason
+        )
+    }
+}
+
+/// Load the bans on addresses for `Ban::find_address` to check connections against
+pub fn load_address_bans() {
+    let loaded = Ban::list()
+        .map(|bans| bans.into_iter().filter(|b| b.address.is_some()).collect())
+        .ok();
+    *lock(&ADDRESS_BANS) = loaded;
+}
+
+/// Load the bans on addresses if they couldn't be loaded before, as when the database was down
+pub fn retry_address_bans() {
+    if lock(&ADDRESS_BANS).is_none() {
+        load_address_bans();
+    }
+}
+
+/// A range of IP addresses in CIDR notation. A lone address is a range of one.
+#[derive(Debug, Clone, Copy)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(s: &str) -> Option<Cidr> {
+        let mut parts = s.splitn(2, '/');
+        let addr = parts.next()?.parse::<IpAddr>().ok()?;
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match parts.next() {
+            Some(p) => p.parse::<u8>().ok().filter(|&p| p <= max)?,
+            None => max,
+        };
+
+        // Store the network address itself, so equivalent ranges look the same
+        let network = match addr {
+            IpAddr::V4(a) => IpAddr::V4(u32::from(a).mask(prefix, 32).into()),
+            IpAddr::V6(a) => IpAddr::V6(u128::from(a).mask(prefix, 128).into()),
+        };
+        Some(Cidr { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Clients connecting over IPv6 sockets may show up with IPv4-mapped addresses
+        let ip = match ip {
+            IpAddr::V6(a) => a
+                .to_ipv4()
+                .filter(|_| a.segments()[5] == 0xffff)
+                .map_or(ip, IpAddr::V4),
+            _ => ip,
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                u32::from(ip).mask(self.prefix, 32) == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                u128::from(ip).mask(self.prefix, 128) == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ToString for Cidr {
+    fn to_string(&self) -> String {
+        format!("{}/{}", self.network, self.prefix)
+    }
+}
+
+// Keep only the leading `prefix` bits of an address
+trait Mask {
+    fn mask(self, prefix: u8, bits: u8) -> Self;
+}
+
+impl Mask for u32 {
+    fn mask(self, prefix: u8, bits: u8) -> u32 {
+        if prefix == 0 {
+            0
+        } else {
+            self & (!0u32 << (bits - prefix))
+        }
+    }
+}
+
+impl Mask for u128 {
+    fn mask(self, prefix: u8, bits: u8) -> u128 {
+        if prefix == 0 {
+            0
+        } else {
+            self & (!0u128 << (bits - prefix))
+        }
+    }
+}
+
+/// Parse a ban length such as "30m", "12h", "7d" or "2w". "perm" means the ban never expires.
+pub fn parse_duration(s: &str) -> Option<Option<Duration>> {
+    if s == "perm" || s == "permanent" {
+        return Some(None);
+    }
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (num, unit) = s.split_at(split);
+    // A ban of no length would be over before it was issued
+    let num = num.parse::<u64>().ok().filter(|&n| n > 0)?;
+    let secs = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Some(Duration::from_secs(num.checked_mul(secs)?)))
+}
+
+// When a ban of the given length would run out, refusing lengths too long to reckon with
+fn expiry(duration: Option<Duration>) -> Result<Option<SystemTime>> {
+    match duration {
+        Some(d) => match SystemTime::now().checked_add(d) {
+            Some(expires) => Ok(Some(expires)),
+            None => Err(Error::Refused(format!(
+                "That is too long for a ban. Use 'perm' for one that never expires.\n"
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Describe a length of time in the largest units that fit, e.g. "2 days, 3 hours"
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    let plural = |n: u64, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+    match (days, hours, mins) {
+        (0, 0, m) => plural(m.max(1), "minute"),
+        (0, h, m) => format!("{}, {}", plural(h, "hour"), plural(m, "minute")),
+        (d, h, _) => format!("{}, {}", plural(d, "day"), plural(h, "hour")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_parses_ranges_and_lone_addresses() {
+        assert_eq!(Cidr::parse("10.1.2.3/8").unwrap().to_string(), "10.0.0.0/8");
+        assert_eq!(Cidr::parse("10.1.2.3").unwrap().to_string(), "10.1.2.3/32");
+        assert_eq!(Cidr::parse("2001:db8::1/32").unwrap().to_string(), "2001:db8::/32");
+        assert!(Cidr::parse("10.1.2.3/33").is_none());
+        assert!(Cidr::parse("10.1.2/8").is_none());
+        assert!(Cidr::parse("10.1.2.3/x").is_none());
+    }
+
+    #[test]
+    fn cidr_contains_addresses_in_its_range() {
+        let range = Cidr::parse("192.168.0.0/16").unwrap();
+        assert!(range.contains(ip("192.168.44.1")));
+        assert!(range.contains(ip("::ffff:192.168.44.1")));
+        assert!(!range.contains(ip("192.169.0.1")));
+        assert!(!range.contains(ip("2001:db8::1")));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(Cidr::parse("2001:db8::/32").unwrap().contains(ip("2001:db8:1::1")));
+    }
+
+    #[test]
+    fn parse_duration_reads_lengths_and_perm() {
+        assert_eq!(parse_duration("30m"), Some(Some(Duration::from_secs(30 * 60))));
+        assert_eq!(parse_duration("2w"), Some(Some(Duration::from_secs(14 * 86400))));
+        assert_eq!(parse_duration("perm"), Some(None));
+        assert_eq!(parse_duration("7"), None);
+        assert_eq!(parse_duration("0m"), None);
+        assert_eq!(parse_duration("0w"), None);
+        assert_eq!(parse_duration("d"), None);
+        assert_eq!(parse_duration("3y"), None);
+        assert_eq!(parse_duration("9999999999999999999w"), None);
+    }
+
+    #[test]
+    fn expiry_refuses_lengths_past_the_end_of_time() {
+        assert!(expiry(Some(Duration::from_secs(u64::MAX))).is_err());
+        assert!(expiry(None).unwrap().is_none());
+    }
+
+    #[test]
+    fn format_duration_uses_the_largest_units() {
+        assert_eq!(format_duration(Duration::from_secs(20)), "1 minute");
+        assert_eq!(format_duration(Duration::from_secs(125 * 60)), "2 hours, 5 minutes");
+        assert_eq!(format_duration(Duration::from_secs(86400 + 3600)), "1 day, 1 hour");
+    }
+}
@@ -0,0 +1,346 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use account::{Account, Role};
+use ban::format_duration;
+use config::{ChannelConf, CONFIG};
+use error::{Error, Result};
+use shared::{lock, SHARE};
+use store::STORE;
+
+lazy_static! {
+    // Every channel in the configuration file, by name
+    static ref CHANNELS: Mutex<BTreeMap<&'static str, Channel>> = Mutex::new(
+        CONFIG
+            .channels
+            .iter()
+            .map(|conf| (&*conf.name, Channel::new(conf)))
+            .collect()
+    );
+}
+
+/// A chat channel as it stands: who listens to it, who may not talk on it, and what was said
+struct Channel {
+    conf: &'static ChannelConf,
+    role: Role,                              // Least privileged role that may listen and talk
+    moderator: Role,                         // Least privileged role that may mute and kick
+    listeners: BTreeSet<String>,             // Accounts in the world that listen to it
+    muted: HashMap<String, Option<Instant>>, // Accounts that may not talk, and until when
+    history: VecDeque<String>,               // The latest messages, oldest first
+}
+
+impl Channel {
+    fn new(conf: &'static ChannelConf) -> Channel {
+        // A mistyped role shouldn't open a channel to more players than it was meant for
+        let role = |name: &str| {
+            Role::parse(name).unwrap_or_else(|| {
+                warn!("Unknown role '{}' on channel {}, allowing only admins", name, conf.name);
+                Role::Admin
+            })
+        };
+        Channel {
+            conf,
+            role: role(&conf.role),
+            moderator: role(&conf.moderator),
+            listeners: BTreeSet::new(),
+            muted: HashMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    // How much longer the account is muted for, if it is. Mutes that have run out are lifted.
+    fn muted(&mut self, account: &str) -> Option<Option<Duration>> {
+        let now = Instant::now();
+        match self.muted.get(account) {
+            Some(&Some(until)) if until <= now => {
+                self.muted.remove(account);
+                None
+            }
+            Some(&until) => Some(until.map(|u| u - now)),
+            None => None,
+        }
+    }
+}
+
+/// The channels in the configuration file, with the least privileged role that may use each
+pub fn configured() -> Vec<(&'static ChannelConf, Role)> {
+    lock(&CHANNELS).values().map(|c| (c.conf, c.role)).collect()
+}
+
+// Work with the named channel, if it exists and the player may use it
+fn with_channel<T, F>(acct: &Account, name: &str, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Channel) -> Result<T>,
+{
+    let name = name.to_lowercase();
+    let mut channels = lock(&CHANNELS);
+    match channels.values_mut().find(|c| c.conf.name.to_lowercase() == name) {
+        Some(channel) if acct.role() >= channel.role => f(channel),
+        _ => Err(Error::Refused(format!(
+            "There is no channel called '{}'. Enter 'channels' to list them.\n",
+            name
+        ))),
+    }
+}
+
+// Send a message to each of the accounts, if they're connected. Only the senders are gathered
+// under the lock, so no lock is held while sending.
+fn send(accounts: &[String], msg: &str) {
+    let txs: Vec<_> = {
+        let players = lock(&SHARE.play_players);
+        accounts.iter().filter_map(|a| players.get(a).cloned()).collect()
+    };
+    for tx in txs {
+        let _ = tx.unbounded_send(Bytes::from(msg));
+    }
+}
+
+// Remember whether the account listens to a channel, for the next time they enter the world.
+// Called with no lock held, so other players' channels don't wait on the database.
+fn save_choice(account: &str, channel: &str, subscribed: bool) {
+    if let Err(e) = STORE.set_subscription(account, channel, subscribed) {
+        error!("Database error saving {}'s choice of channel {}: {}", account, channel, e);
+    }
+}
+
+/// Start the account listening to the channels it has joined, and those it listens to unless it
+/// chooses otherwise, as its character enters the world
+pub fn tune_in(acct: &Account) {
+    let choices: HashMap<String, bool> = STORE
+        .subscriptions(&acct.name)
+        .unwrap_or_else(|e| {
+            error!("Database error loading the channels of {}: {}", acct.name, e);
+            Vec::new()
+        })
+        .into_iter()
+        .collect();
+    for (name, channel) in lock(&CHANNELS).iter_mut() {
+        let listening = choices.get(*name).cloned().unwrap_or(channel.conf.default);
+        if listening && acct.role() >= channel.role {
+            channel.listeners.insert(acct.name.clone());
+        } else {
+            channel.listeners.remove(&acct.name);
+        }
+    }
+}
+
+/// Stop the account listening to any channel, as its character leaves the world
+pub fn forget(account: &str) {
+    for channel in lock(&CHANNELS).values_mut() {
+        channel.listeners.remove(account);
+    }
+}
+
+/// List the channels the player may use, and whether they listen to each
+pub fn list(acct: &Account) -> String {
+    let mut channels = lock(&CHANNELS);
+    let mut list = String::new();
+    for (name, channel) in channels.iter_mut().filter(|&(_, ref c)| acct.role() >= c.role) {
+        let state = if channel.listeners.contains(&acct.name) {
+            "on"
+        } else {
+            "off"
+        };
+        let muted = match channel.muted(&acct.name) {
+            Some(_) => " (you are muted)",
+            None => "",
+        };
+        list.push_str(&format!(
+            "  {:<12} {:<4} {}{}\n",
+            name, state, channel.conf.description, muted
+        ));
+    }
+    if list.is_empty() {
+        return format!("There are no channels.\n");
+    }
+    format!(
+        "Channels:\n{}Talk on one with '<channel> <message...>', or enter its name alone to see \
+         what was said lately.\n",
+        list
+    )
+}
+
+/// Start listening to a channel
+pub fn join(acct: &Account, name: &str) -> Result<String> {
+    let (name, joined) = with_channel(acct, name, |channel| {
+        Ok((&*channel.conf.name, channel.listeners.insert(acct.name.clone())))
+    })?;
+    if !joined {
+        return Ok(format!("You already listen to {}.\n", name));
+    }
+    save_choice(&acct.name, name, true);
+    Ok(format!("You now listen to {}.\n", name))
+}
+
+/// Stop listening to a channel
+pub fn leave(acct: &Account, name: &str) -> Result<String> {
+    let (name, left) = with_channel(acct, name, |channel| {
+        Ok((&*channel.conf.name, channel.listeners.remove(&acct.name)))
+    })?;
+    if !left {
+        return Ok(format!("You don't listen to {}.\n", name));
+    }
+    save_choice(&acct.name, name, false);
+    Ok(format!("You no longer listen to {}.\n", name))
+}
+
+/// Say something on a channel, to everyone listening. Returns the message as the speaker sees it.
+pub fn talk(acct: &Account, speaker: &str, name: &str, msg: &str) -> Result<String> {
+    let (line, listeners) = with_channel(acct, name, |channel| {
+        let name = &channel.conf.name;
+        if !channel.listeners.contains(&acct.name) {
+            return Err(Error::Refused(format!(
+                "You don't listen to {}. Enter 'channel join {}' to start.\n",
+                name, name
+            )));
+        }
+        match channel.muted(&acct.name) {
+            Some(Some(left)) => {
+                return Err(Error::Refused(format!(
+                    "You are muted on {} for another {}.\n",
+                    name,
+                    format_duration(left)
+                )))
+            }
+            Some(None) => return Err(Error::Refused(format!("You are muted on {}.\n", name))),
+            None => {}
+        }
+        let line = format!("[{}] {}: {}\n", name, speaker, msg);
+        channel.history.push_back(line.clone());
+        while channel.history.len() > channel.conf.history {
+            channel.history.pop_front();
+        }
+        let listeners: Vec<String> = channel
+            .listeners
+            .iter()
+            .filter(|&a| a != &acct.name)
+            .cloned()
+            .collect();
+        Ok((line, listeners))
+    })?;
+    send(&listeners, &line);
+    Ok(line)
+}
+
+/// The latest messages on a channel
+pub fn history(acct: &Account, name: &str) -> Result<String> {
+    with_channel(acct, name, |channel| {
+        if channel.history.is_empty() {
+            return Ok(format!("Nothing has been said on {} lately.\n", channel.conf.name));
+        }
+        Ok(format!(
+            "Lately on {}:\n{}",
+            channel.conf.name,
+            channel.history.iter().map(|l| l.as_str()).collect::<String>()
+        ))
+    })
+}
+
+// The role of the account a moderator would act on, looked up before the channels are locked
+fn role_of(account: &str) -> Result<Role> {
+    match STORE.find_account(account) {
+        Ok(found) => Ok(found.map_or(Role::Player, |a| a.role())),
+        Err(e) => {
+            error!("Database error looking up account {}: {}", account, e);
+            Err(Error::Unavailable("Channel moderation"))
+        }
+    }
+}
+
+// Check that the player may moderate the channel, and that the one they would moderate, whose
+// role is given, isn't a moderator too
+fn moderate(
+    acct: &Account,
+    channel: &Channel,
+    account: &str,
+    role: Role,
+    player: &str,
+) -> Result<()> {
+    if acct.role() < channel.moderator {
+        return Err(Error::Refused(format!(
+            "Only moderators of {} may do that.\n",
+            channel.conf.name
+        )));
+    }
+    if account == acct.name {
+        return Err(Error::Refused(format!("You can't do that to yourself.\n")));
+    }
+    if role >= channel.moderator {
+        return Err(Error::Refused(format!(
+            "{} moderates {} too.\n",
+            player, channel.conf.name
+        )));
+    }
+    Ok(())
+}
+
+/// Stop a player talking on a channel, for a while or until they are unmuted
+pub fn mute(
+    acct: &Account,
+    name: &str,
+    account: &str,
+    player: &str,
+    length: Option<Duration>,
+) -> Result<String> {
+    // A length too great to count to is refused, rather than taken as forever
+    let until = match length {
+        Some(l) => match Instant::now().checked_add(l) {
+            Some(until) => Some(until),
+            None => {
+                return Err(Error::Refused(format!(
+                    "That is too long for a mute. Leave out the length for one that never ends.\n"
+                )))
+            }
+        },
+        None => None,
+    };
+    let role = role_of(account)?;
+    let (notice, reply) = with_channel(acct, name, |channel| {
+        moderate(acct, channel, account, role, player)?;
+        let name = &channel.conf.name;
+        channel.muted.insert(account.to_string(), until);
+        let length = length.map_or(String::new(), |l| format!(" for {}", format_duration(l)));
+        Ok((
+            format!("You have been muted on {}{}.\n", name, length),
+            format!("{} is muted on {}{}.\n", player, name, length),
+        ))
+    })?;
+    send(&[account.to_string()], &notice);
+    Ok(reply)
+}
+
+/// Let a muted player talk on a channel again
+pub fn unmute(acct: &Account, name: &str, account: &str, player: &str) -> Result<String> {
+    let role = role_of(account)?;
+    let (notice, reply) = with_channel(acct, name, |channel| {
+        moderate(acct, channel, account, role, player)?;
+        let name = &channel.conf.name;
+        if channel.muted.remove(account).is_none() {
+            return Err(Error::Refused(format!("{} isn't muted on {}.\n", player, name)));
+        }
+        Ok((
+            format!("You may talk on {} again.\n", name),
+            format!("{} may talk on {} again.\n", player, name),
+        ))
+    })?;
+    send(&[account.to_string()], &notice);
+    Ok(reply)
+}
+
+/// Take a player off a channel. They stop listening to it until they choose to join it again.
+pub fn kick(acct: &Account, name: &str, account: &str, player: &str) -> Result<String> {
+    let role = role_of(account)?;
+    let name = with_channel(acct, name, |channel| {
+        moderate(acct, channel, account, role, player)?;
+        let name = &*channel.conf.name;
+        if !channel.listeners.remove(account) {
+            return Err(Error::Refused(format!("{} doesn't listen to {}.\n", player, name)));
+        }
+        Ok(name)
+    })?;
+    save_choice(account, name, false);
+    send(&[account.to_string()], &format!("You have been removed from {}.\n", name));
+    Ok(format!("{} has been removed from {}.\n", player, name))
+}
@@ -0,0 +1,201 @@
+use std::time::SystemTime;
+
+use account::Account;
+use chargen::{self, Draft};
+use config::CONFIG;
+use error::{Error, Result};
+use names;
+use schema::characters;
+use store::STORE;
+use world::{self, Direction, WORLD};
+
+/// A character owned by a player account. Accounts may own several characters, but may only play
+/// one of them at a time.
+#[derive(Queryable, Insertable, AsChangeset, Debug, Clone)]
+#[table_name = "characters"]
+#[primary_key(name)]
+pub struct Character {
+    pub name: String,
+    pub account: String,
+    pub(crate) created: SystemTime,
+    pub(crate) lastplayed: SystemTime,
+    pub description: String,
+    pub race: String,
+    pub class: String,
+    pub strength: i32,
+    pub dexterity: i32,
+    pub constitution: i32,
+    pub intelligence: i32,
+    pub wisdom: i32,
+    pub charisma: i32,
+    pub room: Option<i32>, // Room the character is in, or was last in
+    pub heading: String,   // Compass direction the character faces
+    pub relative: bool,    // Whether exits and maps are shown relative to the heading
+    pub hp: i32,           // Hit points
+    pub mp: i32,           // Mana points
+    pub prompt: String,    // Shown after each command, with tokens expanded by `prompt()`
+}
+
+/// The prompt characters start with
+pub static DEFAULT_PROMPT: &str = "<%h/%Hhp %m/%Mmp> ";
+
+impl Character {
+    /// Create a new character from a finished draft and insert it into the database
+    pub fn new(acct: &Account, draft: &Draft) -> Result<Character> {
+        let owned = STORE
+            .list_characters(&acct.name)
+            .map(|chars| chars.len())
+            .unwrap_or(0);
+        if owned >= CONFIG.game.max_characters {
+            return Err(Error::Refused(format!(
+                "You already have {} characters, the most an account may own.\n",
+                owned
+            )));
+        }
+
+        let (name, description, race, class) = match (
+            draft.name.clone(),
+            draft.description.clone(),
+            draft.race.clone(),
+            draft.class.clone(),
+        ) {
+            (Some(n), Some(d), Some(r), Some(c)) => (n, d, r, c),
+            _ => return Err(Error::Refused(format!("Your character is not finished yet.\n"))),
+        };
+
+        // The rules may have changed since the player chose the name, so check it again
+        names::validate(&name)?;
+        let existing = STORE.find_character_nocase(&name).map_err(|e| {
+            error!("Database error checking for character {}: {}", name, e);
+            Error::Unavailable("Character creation")
+        })?;
+        if existing.is_some() {
+            return Err(Error::Refused(format!(
+                "'{}' already exists. Please choose a different name.\n",
+                name
+            )));
+        }
+
+        // Apply racial bonuses to the attributes the player allocated
+        let bonus = chargen::race(&race).map(|r| r.bonus).unwrap_or([0; 6]);
+        let attrs = draft.attributes();
+        let mut chr = Character {
+            name,
+            account: acct.name.clone(),
+            created: SystemTime::now(),
+            lastplayed: SystemTime::now(),
+            description,
+            race,
+            class,
+            strength: attrs[0] + bonus[0],
+            dexterity: attrs[1] + bonus[1],
+            constitution: attrs[2] + bonus[2],
+            intelligence: attrs[3] + bonus[3],
+            wisdom: attrs[4] + bonus[4],
+            charisma: attrs[5] + bonus[5],
+            room: None,
+            heading: Direction::North.name().to_string(),
+            relative: false,
+            hp: 0,
+            mp: 0,
+            prompt: DEFAULT_PROMPT.to_string(),
+        };
+        // Characters start out whole
+        chr.hp = chr.max_hp();
+        chr.mp = chr.max_mp();
+
+        if let Err(e) = STORE.insert_character(&chr) {
+            error!("Database error creating {:?}: {}", chr, e);
+            Err(Error::Unavailable("Character creation"))
+        } else {
+            info!("{} created new character: {}", chr.account, chr.name);
+            Ok(chr)
+        }
+    }
+
+    /// The most hit points the character can have, from their constitution
+    pub fn max_hp(&self) -> i32 {
+        10 + 2 * self.constitution
+    }
+
+    /// The most mana the character can have, from their intelligence and wisdom
+    pub fn max_mp(&self) -> i32 {
+        self.intelligence + self.wisdom
+    }
+
+    /// The character's prompt, with its tokens replaced by what they stand for: %h and %H for
+    /// current and most hit points, %m and %M for mana, %n for the character's name, %r for the
+    /// room they're in, %f for the way they face, and %% for a percent sign
+    pub fn prompt(&self) -> String {
+        let mut text = String::new();
+        let mut chars = self.prompt.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                text.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('h') => text.push_str(&self.hp.to_string()),
+                Some('H') => text.push_str(&self.max_hp().to_string()),
+                Some('m') => text.push_str(&self.mp.to_string()),
+                Some('M') => text.push_str(&self.max_mp().to_string()),
+                Some('n') => text.push_str(&self.name),
+                Some('r') => {
+                    let room = self.room.and_then(|id| WORLD.room(id));
+                    text.push_str(room.map_or("nowhere", |r| &r.name));
+                }
+                Some('f') => text.push_str(world::facing(self).name()),
+                Some('%') => text.push('%'),
+                Some(other) => {
+                    text.push('%');
+                    text.push(other);
+                }
+                None => text.push('%'),
+            }
+        }
+        text
+    }
+
+    /// List all characters owned by the given account, oldest first
+    pub fn list(acct: &Account) -> Result<Vec<Character>> {
+        STORE.list_characters(&acct.name).map_err(|e| {
+            error!("Database error listing characters for {}: {}", acct.name, e);
+            Error::Unavailable("Character list")
+        })
+    }
+
+    /// Delete one of the given account's characters
+    pub fn delete(acct: &Account, name: &str) -> Result<()> {
+        match STORE.delete_character(&acct.name, name) {
+            Ok(false) => Err(Error::Refused(format!("You have no character named '{}'.\n", name))),
+            Ok(true) => {
+                info!("{} deleted character: {}", acct.name, name);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Database error deleting character {}: {}", name, e);
+                Err(Error::Unavailable("Character deletion"))
+            }
+        }
+    }
+
+    /// Load one of the given account's characters so it can enter the world
+    pub fn select(acct: &Account, name: &str) -> Result<Character> {
+        match STORE.find_character(&acct.name, name) {
+            Ok(Some(mut chr)) => {
+                chr.lastplayed = SystemTime::now();
+                chr.room = Some(WORLD.place(chr.room));
+                if let Err(e) = STORE.update_character(&chr) {
+                    error!("Database error updating {}: {}", chr.name, e);
+                }
+                info!("{} entered the world as {}", acct.name, chr.name);
+                Ok(chr)
+            }
+            Ok(None) => Err(Error::Refused(format!("You have no character named '{}'.\n", name))),
+            Err(e) => {
+                error!("Database error loading character {}: {}", name, e);
+                Err(Error::Unavailable("Character selection"))
+            }
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use account::Account;
+use config::CONFIG;
+use error::{Error, Result};
+use names;
+use schema::character_drafts;
+use store::STORE;
+
+// Value every attribute starts at before the player allocates any points
+pub const BASE_ATTRIBUTE: i32 = 8;
+// Highest value an attribute may be raised to during creation, before racial bonuses
+const MAX_ATTRIBUTE: i32 = 15;
+// Longest short description a character may have
+const MAX_DESCRIPTION: usize = 80;
+
+/// Names of the attributes every character has, in the order they are stored
+pub static ATTRIBUTES: [&str; 6] = [
+    "strength",
+    "dexterity",
+    "constitution",
+    "intelligence",
+    "wisdom",
+    "charisma",
+];
+
+/// A playable race and the bonuses it grants to each attribute
+pub struct Race {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub bonus: [i32; 6],
+}
+
+/// A playable class
+pub struct Class {
+    pub name: &'static str,
+    pub summary: &'static str,
+}
+
+pub static RACES: &[Race] = &[
+    Race {
+        name: "human",
+        summary: "Adaptable and ambitious, good at a little of everything.",
+        bonus: [1, 1, 1, 1, 1, 1],
+    },
+    Race {
+        name: "elf",
+        summary: "Graceful and long-lived, with keen minds and frail bodies.",
+        bonus: [0, 2, -1, 2, 1, 0],
+    },
+    Race {
+        name: "dwarf",
+        summary: "Stout and stubborn, hard to kill and harder to persuade.",
+        bonus: [2, 0, 3, 0, 1, -2],
+    },
+    Race {
+        name: "halfling",
+        summary: "Small, quick and charming, with a knack for staying unnoticed.",
+        bonus: [-2, 3, 1, 0, 0, 2],
+    },
+    Race {
+        name: "orc",
+        summary: "Powerful and fierce, but short-tempered.",
+        bonus: [3, 0, 2, -2, 0, -1],
+    },
+];
+
+pub static CLASSES: &[Class] = &[
+    Class {
+        name: "warrior",
+        summary: "Masters of arms and armour who stand at the front of every fight.",
+    },
+    Class {
+        name: "rogue",
+        summary: "Nimble opportunists who strike from the shadows.",
+    },
+    Class {
+        name: "mage",
+        summary: "Scholars who bend the world to their will with arcane power.",
+    },
+    Class {
+        name: "cleric",
+        summary: "Faithful servants of the gods who heal allies and smite foes.",
+    },
+    Class {
+        name: "ranger",
+        summary: "Wardens of the wilds, at home with a bow and a trail to follow.",
+    },
+];
+
+/// Look up a race by name
+pub fn race(name: &str) -> Option<&'static Race> {
+    RACES.iter().find(|r| r.name == name)
+}
+
+/// A step of the character creation wizard. Each step knows how to describe itself to the player
+/// and how to apply their answer to the draft.
+pub struct Step {
+    pub name: &'static str,
+    prompt: fn(&Draft) -> String,
+    apply: fn(&mut Draft, &str) -> Outcome,
+}
+
+/// What the wizard should do once a step has handled the player's input
+pub enum Outcome {
+    Next,         // The step is complete, move on to the next one
+    Stay(String), // The step needs more input, display the message and prompt again
+    Finish,       // The player has confirmed their choices, create the character
+}
+
+/// The steps of character creation, in the order the player is walked through them
+pub static STEPS: &[Step] = &[
+    Step {
+        name: "name",
+        prompt: name_prompt,
+        apply: name_apply,
+    },
+    Step {
+        name: "description",
+        prompt: description_prompt,
+        apply: description_apply,
+    },
+    Step {
+        name: "race",
+        prompt: race_prompt,
+        apply: race_apply,
+    },
+    Step {
+        name: "class",
+        prompt: class_prompt,
+        apply: class_apply,
+    },
+    Step {
+        name: "attributes",
+        prompt: attributes_prompt,
+        apply: attributes_apply,
+    },
+    Step {
+        name: "confirm",
+        prompt: confirm_prompt,
+        apply: confirm_apply,
+    },
+];
+
+/// A character that is still being created. Drafts are saved after every step so that a player
+/// who disconnects can pick up where they left off.
+#[derive(Queryable, Insertable, AsChangeset, Debug, Clone)]
+#[table_name = "character_drafts"]
+#[primary_key(account)]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct Draft {
+    pub account: String,
+    pub(crate) step: i32,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub race: Option<String>,
+    pub class: Option<String>,
+    pub(crate) strength: i32,
+    pub(crate) dexterity: i32,
+    pub(crate) constitution: i32,
+    pub(crate) intelligence: i32,
+    pub(crate) wisdom: i32,
+    pub(crate) charisma: i32,
+}
+
+impl Draft {
+    /// Load the account's draft in progress, or start a new one
+    pub fn resume(acct: &Account) -> Result<Draft> {
+        let existing = STORE.find_draft(&acct.name).map_err(|e| {
+            error!("Database error loading draft for {}: {}", acct.name, e);
+            Error::Unavailable("Character creation")
+        })?;
+        if let Some(draft) = existing {
+            return Ok(draft);
+        }
+
+        let owned = STORE
+            .list_characters(&acct.name)
+            .map(|chars| chars.len())
+            .unwrap_or(0);
+        if owned >= CONFIG.game.max_characters {
+            return Err(Error::Refused(format!(
+                "You already have {} characters, the most an account may own.\n",
+                owned
+            )));
+        }
+
+        let draft = Draft {
+            account: acct.name.clone(),
+            step: 0,
+            name: None,
+            description: None,
+            race: None,
+            class: None,
+            strength: BASE_ATTRIBUTE,
+            dexterity: BASE_ATTRIBUTE,
+            constitution: BASE_ATTRIBUTE,
+            intelligence: BASE_ATTRIBUTE,
+            wisdom: BASE_ATTRIBUTE,
+            charisma: BASE_ATTRIBUTE,
+        };
+        if let Err(e) = STORE.insert_draft(&draft) {
+            error!("Database error creating {:?}: {}", draft, e);
+            Err(Error::Unavailable("Character creation"))
+        } else {
+            Ok(draft)
+        }
+    }
+
+    /// Save the draft's progress
+    pub fn save(&self) {
+        if let Err(e) = STORE.update_draft(self) {
+            error!("Database error saving {:?}: {}", self, e);
+        }
+    }
+
+    /// Throw the draft away, once it has become a character
+    pub fn discard(&self) {
+        if let Err(e) = STORE.delete_draft(&self.account) {
+            error!("Database error discarding {:?}: {}", self, e);
+        }
+    }
+
+    /// The step the player is currently on
+    pub fn current_step(&self) -> &'static Step {
+        &STEPS[(self.step.max(0) as usize).min(STEPS.len() - 1)]
+    }
+
+    /// Describe the current step to the player
+    pub fn prompt(&self) -> String {
+        let step = self.current_step();
+        format!(
+            "\n[Step {}/{}: {}]\n{}\n(Type 'back' to return to the previous step, or 'abort' to \
+             finish later.)\n> ",
+            self.step + 1,
+            STEPS.len(),
+            step.name,
+            (step.prompt)(self)
+        )
+    }
+
+    /// Hand the player's input to the current step
+    pub fn apply(&mut self, input: &str) -> Outcome {
+        (self.current_step().apply)(self, input)
+    }
+
+    /// Move on to the next step
+    pub fn next(&mut self) {
+        if (self.step as usize) < STEPS.len() - 1 {
+            self.step += 1;
+        }
+    }
+
+    /// Return to the previous step
+    pub fn back(&mut self) {
+        if self.step > 0 {
+            self.step -= 1;
+        }
+    }
+
+    /// The player's allocated attributes, before racial bonuses
+    pub fn attributes(&self) -> [i32; 6] {
+        [
+            self.strength,
+            self.dexterity,
+            self.constitution,
+            self.intelligence,
+            self.wisdom,
+            self.charisma,
+        ]
+    }
+
+    fn attribute_mut(&mut self, idx: usize) -> &mut i32 {
+        match idx {
+            0 => &mut self.strength,
+            1 => &mut self.dexterity,
+            2 => &mut self.constitution,
+            3 => &mut self.intelligence,
+            4 => &mut self.wisdom,
+            _ => &mut self.charisma,
+        }
+    }
+
+    /// Points the player has yet to allocate
+    fn points_left(&self) -> i32 {
+        let spent: i32 = self.attributes().iter().map(|a| a - BASE_ATTRIBUTE).sum();
+        CONFIG.game.attribute_points - spent
+    }
+}
+
+// Remind the player of what they chose last time, in case they came back to this step
+fn keep_hint(value: &Option<String>) -> String {
+    match *value {
+        Some(ref v) => format!(" Press enter to keep '{}'.", v),
+        None => String::new(),
+    }
+}
+
+fn name_prompt(draft: &Draft) -> String {
+    format!(
+        "What is your character's name?{}",
+        keep_hint(&draft.name)
+    )
+}
+
+fn name_apply(draft: &mut Draft, input: &str) -> Outcome {
+    if input.is_empty() {
+        return match draft.name {
+            Some(_) => Outcome::Next,
+            None => Outcome::Stay(format!("Your character needs a name.\n")),
+        };
+    }
+    if let Err(e) = names::validate(input) {
+        return Outcome::Stay(e.notice());
+    }
+
+    if let Ok(Some(_)) = STORE.find_character_nocase(input) {
+        return Outcome::Stay(format!(
+            "'{}' already exists. Please choose a different name.\n",
+            input
+        ));
+    }
+
+    draft.name = Some(input.to_string());
+    Outcome::Next
+}
+
+fn description_prompt(draft: &Draft) -> String {
+    format!(
+        "Describe your character in a few words, as others will see them. For example: \
+         'a tall woman with a scarred face'.{}",
+        keep_hint(&draft.description)
+    )
+}
+
+fn description_apply(draft: &mut Draft, input: &str) -> Outcome {
+    if input.is_empty() {
+        return match draft.description {
+            Some(_) => Outcome::Next,
+            None => Outcome::Stay(format!("Your character needs a description.\n")),
+        };
+    }
+    if input.len() > MAX_DESCRIPTION {
+        return Outcome::Stay(format!(
+            "Descriptions may be at most {} characters long.\n",
+            MAX_DESCRIPTION
+        ));
+    }
+
+    draft.description = Some(input.to_string());
+    Outcome::Next
+}
+
+fn race_prompt(draft: &Draft) -> String {
+    let races: String = RACES
+        .iter()
+        .map(|r| format!("  {:<10} - {}\n", r.name, r.summary))
+        .collect();
+    format!("Choose a race:\n{}{}", races, keep_hint(&draft.race))
+}
+
+fn race_apply(draft: &mut Draft, input: &str) -> Outcome {
+    if input.is_empty() && draft.race.is_some() {
+        return Outcome::Next;
+    }
+    let input = input.to_lowercase();
+    match RACES.iter().find(|r| !input.is_empty() && r.name.starts_with(&*input)) {
+        Some(race) => {
+            draft.race = Some(race.name.to_string());
+            Outcome::Next
+        }
+        None => Outcome::Stay(format!("'{}' is not a race you may choose.\n", input)),
+    }
+}
+
+fn class_prompt(draft: &Draft) -> String {
+    let classes: String = CLASSES
+        .iter()
+        .map(|c| format!("  {:<10} - {}\n", c.name, c.summary))
+        .collect();
+    format!("Choose a class:\n{}{}", classes, keep_hint(&draft.class))
+}
+
+fn class_apply(draft: &mut Draft, input: &str) -> Outcome {
+    if input.is_empty() && draft.class.is_some() {
+        return Outcome::Next;
+    }
+    let input = input.to_lowercase();
+    match CLASSES.iter().find(|c| !input.is_empty() && c.name.starts_with(&*input)) {
+        Some(class) => {
+            draft.class = Some(class.name.to_string());
+            Outcome::Next
+        }
+        None => Outcome::Stay(format!("'{}' is not a class you may choose.\n", input)),
+    }
+}
+
+fn attributes_prompt(draft: &Draft) -> String {
+    let attrs: String = ATTRIBUTES
+        .iter()
+        .zip(draft.attributes().iter())
+        .map(|(name, value)| format!("  {:<13} {:>2}\n", name, value))
+        .collect();
+    format!(
+        "Allocate your attribute points. You have {} left to spend.\n{}\
+         Enter an attribute and an amount to add or remove (e.g. 'str 2' or 'wis -1'),\n\
+         'reset' to start over, or 'done' when you have spent all of your points.",
+        draft.points_left(),
+        attrs
+    )
+}
+
+fn attributes_apply(draft: &mut Draft, input: &str) -> Outcome {
+    let mut words = input.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("done"), None) => {
+            if draft.points_left() == 0 {
+                Outcome::Next
+            } else {
+                Outcome::Stay(format!(
+                    "You still have {} points to spend.\n",
+                    draft.points_left()
+                ))
+            }
+        }
+        (Some("reset"), None) => {
+            for idx in 0..ATTRIBUTES.len() {
+                *draft.attribute_mut(idx) = BASE_ATTRIBUTE;
+            }
+            Outcome::Stay(String::new())
+        }
+        (Some(attr), Some(amount)) => {
+            let attr = attr.to_lowercase();
+            let idx = match ATTRIBUTES.iter().position(|a| a.starts_with(&*attr)) {
+                Some(idx) => idx,
+                None => return Outcome::Stay(format!("'{}' is not an attribute.\n", attr)),
+            };
+            let amount = match amount.parse::<i32>() {
+                Ok(amount) => amount,
+                Err(_) => return Outcome::Stay(format!("'{}' is not a number.\n", amount)),
+            };
+            let value = draft.attributes()[idx] + amount;
+            if amount > draft.points_left() {
+                Outcome::Stay(format!(
+                    "You only have {} points left to spend.\n",
+                    draft.points_left()
+                ))
+            } else if value < BASE_ATTRIBUTE || value > MAX_ATTRIBUTE {
+                Outcome::Stay(format!(
+                    "Attributes must stay between {} and {}.\n",
+                    BASE_ATTRIBUTE, MAX_ATTRIBUTE
+                ))
+            } else {
+                *draft.attribute_mut(idx) = value;
+                Outcome::Stay(String::new())
+            }
+        }
+        _ => Outcome::Stay(format!("Usage: <attribute> <amount>, reset or done\n")),
+    }
+}
+
+fn confirm_prompt(draft: &Draft) -> String {
+    let none = String::from("(none)");
+    let bonus = draft
+        .race
+        .as_ref()
+        .and_then(|r| race(r))
+        .map(|r| r.bonus)
+        .unwrap_or([0; 6]);
+    let attrs: String = ATTRIBUTES
+        .iter()
+        .zip(draft.attributes().iter().zip(bonus.iter()))
+        .map(|(name, (value, bonus))| format!("  {:<13} {:>2}\n", name, value + bonus))
+        .collect();
+    format!(
+        "Please review your character:\n\
+         \x20 Name:        {}\n\
+         \x20 Description: {}\n\
+         \x20 Race:        {}\n\
+         \x20 Class:       {}\n\
+         {}\
+         Create this character? (yes/no)",
+        draft.name.as_ref().unwrap_or(&none),
+        draft.description.as_ref().unwrap_or(&none),
+        draft.race.as_ref().unwrap_or(&none),
+        draft.class.as_ref().unwrap_or(&none),
+        attrs
+    )
+}
+
+fn confirm_apply(_draft: &mut Draft, input: &str) -> Outcome {
+    match input.to_lowercase().as_str() {
+        "y" | "yes" => Outcome::Finish,
+        "n" | "no" => Outcome::Stay(format!(
+            "Use 'back' to change your choices, or 'abort' to finish later.\n"
+        )),
+        _ => Outcome::Stay(format!("Please answer 'yes' or 'no'.\n")),
+    }
+}
@@ -0,0 +1,409 @@
+use config::CONFIG;
+
+/// The kinds of argument a command can take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Literal, // The parameter's own name, as in "ban list"
+    Word,    // A single word
+    Text,    // A single word, or several in double quotes
+    Number,  // A whole number
+    Player,  // The name of a player's character
+    Target,  // Something in the world, optionally numbered as in "2.sword"
+    Rest,    // Everything left on the line
+}
+
+/// One argument in a command's grammar
+#[derive(Debug, Clone, Copy)]
+pub struct Param {
+    name: &'static str,
+    kind: Kind,
+    optional: bool,
+}
+
+impl Param {
+    fn new(name: &'static str, kind: Kind) -> Param {
+        Param {
+            name,
+            kind,
+            optional: false,
+        }
+    }
+
+    pub fn literal(name: &'static str) -> Param {
+        Param::new(name, Kind::Literal)
+    }
+
+    pub fn word(name: &'static str) -> Param {
+        Param::new(name, Kind::Word)
+    }
+
+    pub fn text(name: &'static str) -> Param {
+        Param::new(name, Kind::Text)
+    }
+
+    pub fn number(name: &'static str) -> Param {
+        Param::new(name, Kind::Number)
+    }
+
+    pub fn player(name: &'static str) -> Param {
+        Param::new(name, Kind::Player)
+    }
+
+    pub fn target(name: &'static str) -> Param {
+        Param::new(name, Kind::Target)
+    }
+
+    pub fn rest(name: &'static str) -> Param {
+        Param::new(name, Kind::Rest)
+    }
+
+    /// The same parameter, but one that may be left out
+    pub fn optional(mut self) -> Param {
+        self.optional = true;
+        self
+    }
+
+    // How the parameter is shown in a usage line
+    fn usage(&self) -> String {
+        let shown = match self.kind {
+            Kind::Literal => self.name.to_string(),
+            Kind::Rest => format!("<{}...>", self.name),
+            _ => format!("<{}>", self.name),
+        };
+        if self.optional {
+            format!("[{}]", shown)
+        } else {
+            shown
+        }
+    }
+}
+
+/// The usage line for one form of a command, as in "login <name> <password>"
+pub fn usage(name: &str, form: &[Param]) -> String {
+    let mut line = name.to_string();
+    for param in form {
+        line.push(' ');
+        line.push_str(&param.usage());
+    }
+    line
+}
+
+/// The "Usage:" message listing the given usage lines
+pub fn usage_message(lines: &[String]) -> String {
+    format!("Usage: {}\n", lines.join("\n       "))
+}
+
+// A parsed argument's value
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String), // Literals, words, quoted text, player names and the rest of the line
+    Number(i64),
+    Target(usize, String),
+}
+
+/// The arguments a player gave a command, by parameter name
+#[derive(Debug, Default)]
+pub struct Args {
+    values: Vec<(&'static str, Value)>,
+}
+
+impl Args {
+    /// Whether the argument was given. For literals, whether the player entered that word.
+    pub fn has(&self, name: &str) -> bool {
+        self.values.iter().any(|&(n, _)| n == name)
+    }
+
+    /// A text argument, if it was given
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|&&(n, _)| n == name).and_then(|&(_, ref v)| match *v {
+            Value::Text(ref s) => Some(&**s),
+            _ => None,
+        })
+    }
+
+    /// A text argument the grammar requires, so that it's always there
+    pub fn word(&self, name: &str) -> &str {
+        self.get(name).unwrap_or("")
+    }
+
+    pub fn number(&self, name: &str) -> Option<i64> {
+        self.values.iter().find(|&&(n, _)| n == name).and_then(|&(_, ref v)| match *v {
+            Value::Number(n) => Some(n),
+            _ => None,
+        })
+    }
+
+    /// Which of several like-named things is meant, counting from 1, and its name
+    pub fn target(&self, name: &str) -> Option<(usize, &str)> {
+        self.values.iter().find(|&&(n, _)| n == name).and_then(|&(_, ref v)| match *v {
+            Value::Target(nth, ref s) => Some((nth, &**s)),
+            _ => None,
+        })
+    }
+}
+
+// Walks through a line of input, one argument at a time
+struct Cursor<'a> {
+    rest: &'a str,
+    taken: usize,    // Arguments taken so far, to judge which form the player was attempting
+    committed: bool, // Whether one of the form's literals was entered
+}
+
+impl<'a> Cursor<'a> {
+    fn is_empty(&self) -> bool {
+        self.rest.trim().is_empty()
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.rest.split_whitespace().next()
+    }
+
+    fn word(&mut self) -> Option<&'a str> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        self.rest = &rest[end..];
+        self.taken += 1;
+        Some(&rest[..end])
+    }
+
+    // A quoted string, or a single word if it doesn't begin with a quote
+    fn text(&mut self) -> Result<Option<&'a str>, String> {
+        let rest = self.rest.trim_start();
+        if !rest.starts_with('"') {
+            return Ok(self.word());
+        }
+        match rest[1..].find('"') {
+            Some(end) => {
+                self.rest = &rest[end + 2..];
+                self.taken += 1;
+                Ok(Some(&rest[1..end + 1]))
+            }
+            None => Err(format!("There's no closing quote after {}.", rest)),
+        }
+    }
+
+    fn remainder(&mut self) -> Option<&'a str> {
+        let rest = self.rest.trim();
+        self.rest = "";
+        if rest.is_empty() {
+            None
+        } else {
+            self.taken += 1;
+            Some(rest)
+        }
+    }
+}
+
+// Why the input doesn't fit a form
+struct Misfit {
+    taken: usize,    // Arguments that fit before the one that didn't
+    committed: bool, // Whether the player entered one of the form's literals, and so meant it
+    reason: String,
+}
+
+// Parse the input against one form, or say why it doesn't fit
+fn parse_form(form: &[Param], input: &str) -> Result<Args, Misfit> {
+    let mut cur = Cursor {
+        rest: input,
+        taken: 0,
+        committed: false,
+    };
+    fit(form, &mut cur).map_err(|reason| Misfit {
+        taken: cur.taken,
+        committed: cur.committed,
+        reason,
+    })
+}
+
+// Take the form's arguments from the cursor
+fn fit(form: &[Param], cur: &mut Cursor) -> Result<Args, String> {
+    let mut args = Args::default();
+    for param in form {
+        if param.kind == Kind::Literal {
+            match cur.peek() {
+                Some(w) if w.eq_ignore_ascii_case(param.name) => {
+                    cur.word();
+                    args.values.push((param.name, Value::Text(param.name.to_string())));
+                    cur.committed = cur.committed || !param.optional;
+                }
+                _ if param.optional => {}
+                Some(w) => return Err(format!("'{}' isn't '{}'.", w, param.name)),
+                None => return Err(format!("Missing '{}'.", param.name)),
+            }
+            continue;
+        }
+        if cur.is_empty() {
+            if param.optional {
+                continue;
+            }
+            return Err(format!("Missing {}.", param.usage()));
+        }
+        let value = match param.kind {
+            Kind::Word => cur.word().map(|w| Value::Text(w.to_string())),
+            Kind::Text => cur.text()?.map(|w| Value::Text(w.to_string())),
+            Kind::Rest => cur.remainder().map(|w| Value::Text(w.to_string())),
+            Kind::Number => match cur.word() {
+                Some(w) => match w.parse() {
+                    Ok(n) => Some(Value::Number(n)),
+                    Err(_) => return Err(format!("'{}' isn't a number.", w)),
+                },
+                None => None,
+            },
+            Kind::Player => match cur.word() {
+                Some(w) if is_name(w) => Some(Value::Text(w.to_string())),
+                Some(w) => return Err(format!("'{}' isn't a player's name.", w)),
+                None => None,
+            },
+            Kind::Target => match cur.word() {
+                Some(w) => match target(w) {
+                    Some((nth, name)) => Some(Value::Target(nth, name.to_string())),
+                    None => return Err(format!("'{}' isn't a thing's name.", w)),
+                },
+                None => None,
+            },
+            Kind::Literal => unreachable!(),
+        };
+        if let Some(value) = value {
+            args.values.push((param.name, value));
+        }
+    }
+    match cur.peek() {
+        Some(extra) => Err(format!("'{}' wasn't expected.", extra)),
+        None => Ok(args),
+    }
+}
+
+/// Parse a command's arguments against the first of its forms that fits. When none do, the
+/// player is told what was wrong with the form they seemed to be attempting, and how the
+/// command is used.
+pub fn parse(name: &str, forms: &[Vec<Param>], input: &str) -> Result<Args, String> {
+    let mut best: Option<(usize, String)> = None;
+    for form in forms {
+        match parse_form(form, input) {
+            Ok(args) => return Ok(args),
+            // "delete account" without a password is a mistake, not a character called "account"
+            Err(Misfit {
+                committed: true,
+                taken,
+                reason,
+            }) => {
+                best = Some((taken, reason));
+                break;
+            }
+            Err(Misfit { taken, reason, .. }) => {
+                if best.as_ref().map_or(true, |&(most, _)| taken > most) {
+                    best = Some((taken, reason));
+                }
+            }
+        }
+    }
+    // If the player got nowhere with any of several forms, there's no telling which they meant
+    let reason = match best {
+        Some((taken, _)) if taken == 0 && forms.len() > 1 => String::new(),
+        Some((_, reason)) => format!("{}\n", reason),
+        None => String::new(),
+    };
+    let lines: Vec<String> = forms.iter().map(|f| usage(name, f)).collect();
+    Err(format!("{}{}", reason, usage_message(&lines)))
+}
+
+// Whether the word could be a character's name
+fn is_name(word: &str) -> bool {
+    word.chars().next().map_or(false, |c| c.is_ascii_alphabetic())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || CONFIG.names.extra_chars.contains(c))
+}
+
+// Split "2.sword" into which one and what, or take "sword" to mean the first
+fn target(word: &str) -> Option<(usize, &str)> {
+    let (nth, name) = match word.find('.') {
+        Some(dot) => match word[..dot].parse() {
+            Ok(nth) if nth > 0 => (nth, &word[dot + 1..]),
+            _ => return None,
+        },
+        None => (1, word),
+    };
+    if name.is_empty() {
+        None
+    } else {
+        Some((nth, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delete_forms() -> Vec<Vec<Param>> {
+        vec![
+            vec![Param::literal("account"), Param::text("password")],
+            vec![Param::player("name")],
+        ]
+    }
+
+    #[test]
+    fn parse_takes_words_quoted_text_and_the_rest() {
+        let form = vec![
+            Param::word("name"),
+            Param::text("password"),
+            Param::number("count"),
+            Param::rest("reason"),
+        ];
+        let args = parse("cmd", &[form], "bob \"two words\" 3 for no reason").unwrap();
+        assert_eq!(args.word("name"), "bob");
+        assert_eq!(args.word("password"), "two words");
+        assert_eq!(args.number("count"), Some(3));
+        assert_eq!(args.word("reason"), "for no reason");
+    }
+
+    #[test]
+    fn parse_takes_numbered_targets() {
+        let forms = vec![vec![Param::target("thing")]];
+        let args = parse("get", &forms, "2.sword").unwrap();
+        assert_eq!(args.target("thing"), Some((2, "sword")));
+        let args = parse("get", &forms, "sword").unwrap();
+        assert_eq!(args.target("thing"), Some((1, "sword")));
+        for bad in &["0.sword", "2.", "x.sword", "-1.sword"] {
+            let err = parse("get", &forms, bad).unwrap_err();
+            assert!(err.starts_with(&format!("'{}' isn't a thing's name.\n", bad)));
+        }
+    }
+
+    #[test]
+    fn parse_skips_missing_optional_parameters() {
+        let form = vec![Param::word("channel"), Param::word("length").optional()];
+        let args = parse("mute", &[form], "gossip").unwrap();
+        assert!(!args.has("length"));
+    }
+
+    #[test]
+    fn parse_picks_the_form_that_fits() {
+        let args = parse("delete", &delete_forms(), "account \"my secret\"").unwrap();
+        assert!(args.has("account"));
+        assert_eq!(args.word("password"), "my secret");
+        let args = parse("delete", &delete_forms(), "Alice").unwrap();
+        assert_eq!(args.word("name"), "Alice");
+    }
+
+    #[test]
+    fn parse_holds_to_a_form_whose_literal_was_entered() {
+        let err = parse("delete", &delete_forms(), "account").unwrap_err();
+        assert!(err.starts_with("Missing <password>.\n"));
+        assert!(err.contains("Usage: delete account <password>"));
+    }
+
+    #[test]
+    fn parse_explains_what_does_not_fit() {
+        let forms = vec![vec![Param::number("id")]];
+        let err = parse("lift", &forms, "seven").unwrap_err();
+        assert!(err.starts_with("'seven' isn't a number.\n"));
+        let err = parse("lift", &forms, "7 8").unwrap_err();
+        assert!(err.starts_with("'8' wasn't expected.\n"));
+        let err = parse("say", &[vec![Param::text("words")]], "\"unclosed").unwrap_err();
+        assert!(err.starts_with("There's no closing quote"));
+    }
+}
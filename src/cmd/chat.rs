@@ -0,0 +1,124 @@
+use super::*;
+use ban;
+use channel;
+use config::ChannelConf;
+use world;
+
+use self::Handler::ForAccount;
+use self::Stage::*;
+
+// Add the commands for chat channels: one for each channel, and one to manage them
+pub(super) fn add_commands(reg: &mut Registry) {
+    for (conf, role) in channel::configured() {
+        reg.add(Talk { conf, role });
+    }
+    reg.add(
+        Cmd::new(
+            "channel",
+            &[Playing],
+            "List chat channels, join or leave them, or moderate them",
+            ForAccount(manage),
+        ).alias("channels")
+            .args(&[])
+            .args(&[Param::literal("join"), Param::word("channel")])
+            .args(&[Param::literal("leave"), Param::word("channel")])
+            .args(&[Param::literal("history"), Param::word("channel")])
+            .args(&[
+                Param::literal("mute"),
+                Param::word("channel"),
+                Param::player("player"),
+                Param::word("length").optional(),
+            ])
+            .args(&[Param::literal("unmute"), Param::word("channel"), Param::player("player")])
+            .args(&[Param::literal("kick"), Param::word("channel"), Param::player("player")]),
+    );
+}
+
+/// A channel's name entered as a command, to talk on it, or alone to see what was said lately
+struct Talk {
+    conf: &'static ChannelConf,
+    role: Role, // Least privileged role that may use the channel
+}
+
+impl Command for Talk {
+    fn name(&self) -> &'static str {
+        &self.conf.name
+    }
+
+    fn stages(&self) -> &[Stage] {
+        &[Playing]
+    }
+
+    fn role(&self) -> Role {
+        self.role
+    }
+
+    fn help(&self) -> &'static str {
+        &self.conf.description
+    }
+
+    fn usage(&self) -> Vec<String> {
+        vec![args::usage(self.name(), &[Param::rest("message").optional()])]
+    }
+
+    // Behind every other command, so a channel's name doesn't make their abbreviations ambiguous
+    fn priority(&self) -> i32 {
+        -1
+    }
+
+    fn execute(&self, ctx: &mut Context) -> Action {
+        let (acct, chr) = match (ctx.acct.as_ref(), ctx.chr.as_ref()) {
+            (Some(acct), Some(chr)) => (acct, chr),
+            _ => return Noop(format!("You can't do that right now.\n")),
+        };
+        let msg = ctx.line.trim();
+        let result = if msg.is_empty() {
+            channel::history(acct, self.name())
+        } else {
+            channel::talk(acct, &chr.name, self.name(), msg)
+        };
+        match result {
+            Ok(s) => Noop(s),
+            Err(e) => Noop(e.notice()),
+        }
+    }
+}
+
+/// List the channels, join or leave one, look back over one, or moderate one
+fn manage(acct: &mut Account, args: &Args) -> Action {
+    let name = args.word("channel");
+    let result = if args.has("join") {
+        channel::join(acct, name)
+    } else if args.has("leave") {
+        channel::leave(acct, name)
+    } else if args.has("history") {
+        channel::history(acct, name)
+    } else if args.has("player") {
+        let (account, player, _) = match world::find_player(args.word("player")) {
+            Some(found) => found,
+            None => return comm::not_in_world(args.word("player")),
+        };
+        if args.has("unmute") {
+            channel::unmute(acct, name, &account, &player)
+        } else if args.has("kick") {
+            channel::kick(acct, name, &account, &player)
+        } else {
+            match args.get("length").map(ban::parse_duration) {
+                None => channel::mute(acct, name, &account, &player, None),
+                Some(Some(length)) => channel::mute(acct, name, &account, &player, length),
+                Some(None) => {
+                    return Noop(format!(
+                        "'{}' isn't a length of time, such as 30m, 12h, 7d, 2w or perm.\n",
+                        args.word("length")
+                    ))
+                }
+            }
+        }
+    } else {
+        return Noop(channel::list(acct));
+    };
+    match result {
+        Ok(s) => Noop(s),
+        Err(e) => Noop(e.notice()),
+    }
+}
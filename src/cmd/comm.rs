@@ -0,0 +1,167 @@
+use super::*;
+use character::Character;
+use shared::{lock, SHARE};
+use store::STORE;
+use world::{self, WORLD};
+
+use self::Handler::ForCharacter;
+use self::Stage::*;
+
+// Add the commands for talking to other players
+pub(super) fn add_commands(reg: &mut Registry) {
+    reg.add(
+        Cmd::new("emote", &[Playing], "Act something out for the room to see", ForCharacter(emote))
+            .args(&[Param::rest("action")]),
+    );
+    reg.add(
+        Cmd::new(
+            "reply",
+            &[Playing],
+            "Answer whoever last told you something",
+            ForCharacter(reply),
+        ).args(&[Param::rest("message")]),
+    );
+    reg.add(
+        Cmd::new("say", &[Playing], "Say something to everyone in the room", ForCharacter(say))
+            .args(&[Param::rest("message")]),
+    );
+    reg.add(
+        Cmd::new(
+            "shout",
+            &[Playing],
+            "Shout something for the whole area to hear",
+            ForCharacter(shout),
+        ).args(&[Param::rest("message")]),
+    );
+    reg.add(
+        Cmd::new(
+            "tell",
+            &[Playing],
+            "Say something privately to a player anywhere in the world",
+            ForCharacter(tell),
+        ).args(&[Param::player("player"), Param::rest("message")]),
+    );
+    reg.add(
+        Cmd::new(
+            "whisper",
+            &[Playing],
+            "Say something privately to a player in the same room",
+            ForCharacter(whisper),
+        ).args(&[Param::player("player"), Param::rest("message")]),
+    );
+}
+
+// How a message is spoken, to the speaker and of them, judged by how it ends
+fn speech(msg: &str) -> (&'static str, &'static str) {
+    match msg.chars().last() {
+        Some('?') => ("ask", "asks"),
+        Some('!') => ("exclaim", "exclaims"),
+        _ => ("say", "says"),
+    }
+}
+
+// Tell the player why someone they named can't be reached
+pub(super) fn not_in_world(name: &str) -> Action {
+    match STORE.find_character_nocase(name) {
+        Ok(Some(chr)) => Noop(format!("{} is not in the world right now.\n", chr.name)),
+        Ok(None) => Noop(format!("There is no one named {}.\n", name)),
+        Err(e) => {
+            error!("Database error looking up character {}: {}", name, e);
+            Noop(format!("{} is not in the world right now.\n", name))
+        }
+    }
+}
+
+/// Say something to everyone in the room
+fn say(chr: &mut Character, args: &Args) -> Action {
+    let msg = args.word("message");
+    let (verb, verbs) = speech(msg);
+    let room = WORLD.place(chr.room);
+    world::tell_room(room, &chr.account, &format!("{} {}, '{}'\n", chr.name, verbs, msg));
+    Noop(format!("You {}, '{}'\n", verb, msg))
+}
+
+/// Act something out, as in "emote waves" showing "Bob waves." to the room
+fn emote(chr: &mut Character, args: &Args) -> Action {
+    let action = args.word("action");
+    // "emote 's hat blows away" runs on from the name, as "Bob's hat blows away."
+    let space = if action.starts_with('\'') || action.starts_with(',') {
+        ""
+    } else {
+        " "
+    };
+    let stop = if action.ends_with(|c: char| c.is_alphanumeric()) {
+        "."
+    } else {
+        ""
+    };
+    let msg = format!("{}{}{}{}\n", chr.name, space, action, stop);
+    world::tell_room(WORLD.place(chr.room), &chr.account, &msg);
+    Noop(msg)
+}
+
+/// Say something privately to a player anywhere in the world
+fn tell(chr: &mut Character, args: &Args) -> Action {
+    send_tell(chr, args.word("player"), args.word("message"))
+}
+
+/// Answer whoever last told the character something
+fn reply(chr: &mut Character, args: &Args) -> Action {
+    let teller = lock(&SHARE.replies).get(&chr.name).cloned();
+    match teller {
+        Some(name) => send_tell(chr, &name, args.word("message")),
+        None => Noop(format!("Nobody has told you anything to reply to.\n")),
+    }
+}
+
+// Pass a private message to the named player, so that they can reply to it
+fn send_tell(chr: &Character, name: &str, msg: &str) -> Action {
+    let (account, name, _) = match world::find_player(name) {
+        Some(found) => found,
+        None => return not_in_world(name),
+    };
+    if account == chr.account {
+        return Noop(format!("You mutter something to yourself.\n"));
+    }
+    if !world::tell_player(&account, &format!("{} tells you, '{}'\n", chr.name, msg)) {
+        return Noop(format!("{} can't hear you right now.\n", name));
+    }
+    lock(&SHARE.replies).insert(name.clone(), chr.name.clone());
+    Noop(format!("You tell {}, '{}'\n", name, msg))
+}
+
+/// Say something privately to a player in the same room. Others there see it happen, but can't
+/// make out what was said.
+fn whisper(chr: &mut Character, args: &Args) -> Action {
+    let msg = args.word("message");
+    let room = WORLD.place(chr.room);
+    let (account, name, there) = match world::find_player(args.word("player")) {
+        Some(found) => found,
+        None => return not_in_world(args.word("player")),
+    };
+    if account == chr.account {
+        return Noop(format!("You mutter something to yourself.\n"));
+    }
+    if there != room {
+        return Noop(format!("{} isn't here.\n", name));
+    }
+    if !world::tell_player(&account, &format!("{} whispers to you, '{}'\n", chr.name, msg)) {
+        return Noop(format!("{} can't hear you right now.\n", name));
+    }
+    world::tell_onlookers(
+        room,
+        &[&chr.account, &account],
+        &format!("{} whispers something to {}.\n", chr.name, name),
+    );
+    Noop(format!("You whisper to {}, '{}'\n", name, msg))
+}
+
+/// Shout something for everyone in the zone to hear
+fn shout(chr: &mut Character, args: &Args) -> Action {
+    let msg = args.word("message");
+    let room = WORLD.place(chr.room);
+    if let Some(r) = WORLD.room(room) {
+        world::tell_zone(&r.zone, &chr.account, &format!("{} shouts, '{}'\n", chr.name, msg));
+    }
+    Noop(format!("You shout, '{}'\n", msg))
+}
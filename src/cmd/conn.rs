@@ -1,40 +1,54 @@
-use std::collections::BTreeMap;
-use std::iter::Iterator;
-use std::str::SplitWhitespace;
-
+use super::*;
 use account::*;
 use shared::*;
 
-type ConnFn = fn(&mut SplitWhitespace) -> ConnAction;
+use self::Handler::ForGuest;
+use self::Stage::*;
 
-#[derive(Debug)]
-pub enum ConnAction {
-    Disconnect,
-    Login(Account, String),
-    Noop(String),
-}
-
-pub use self::ConnAction::*;
-
-/// Tables of recognized commands before login
-lazy_static! {
-    static ref CONN_CMDS: BTreeMap<&'static str, ConnFn> = {
-        let mut m = BTreeMap::new();
-        m.insert("help", help as ConnFn);
-        m.insert("quit", quit as ConnFn);
-        m.insert("register", register as ConnFn);
-        m.insert("login", login as ConnFn);
-        //m.insert("stats", stats as ConnFn);
-        m.insert("who", who as ConnFn);
-        m
-    };
+// Add the commands available before login
+pub(super) fn add_commands(reg: &mut Registry) {
+    reg.add(
+        Cmd::new(
+            "forgot",
+            &[Connected],
+            "Reset a forgotten password",
+            ForGuest(forgot),
+        ).args(&[Param::word("name")])
+            .args(&[
+                Param::word("name"),
+                Param::word("code"),
+                Param::text("new password"),
+            ])
+            .secret(),
+    );
+    reg.add(Cmd::new(
+        "quit",
+        &[Connected, Idle, Playing],
+        "Say goodbye and disconnect",
+        ForGuest(quit),
+    ));
+    reg.add(
+        Cmd::new(
+            "register",
+            &[Connected],
+            "Register as a new player",
+            ForGuest(register),
+        ).args(&[Param::word("name"), Param::text("password")])
+            .secret(),
+    );
+    reg.add(
+        Cmd::new("login", &[Connected], "Log in to your account", ForGuest(login))
+            .args(&[Param::word("name"), Param::text("password")])
+            .secret(),
+    );
+    reg.add(Cmd::new("who", &[Connected], "List players logged in", ForGuest(who)));
 }
 
 /// Display a list of currently logged in players
-fn who(_line: &mut SplitWhitespace) -> ConnAction {
-    let playing = SHARE.play_players.lock().unwrap();
+fn who(_args: &Args) -> Action {
+    let playing = lock(&SHARE.play_players);
     let play_cnt = playing.len();
-    let total = SHARE.conn_players.lock().unwrap().len() + play_cnt;
+    let total = lock(&SHARE.conn_players).len() + play_cnt;
     let namelist: String = playing.keys().map(|u| format!("  {}\n", u)).collect();
     Noop(format!(
         "Total connected: {}. Logged in: {}\n{}",
@@ -42,66 +56,96 @@ fn who(_line: &mut SplitWhitespace) -> ConnAction {
     ))
 }
 
-/// Display the splash text
-fn help(_line: &mut SplitWhitespace) -> ConnAction {
-    Noop(SPLASH.to_string())
+/// Mail a password reset code to a player, or use one to set a new password
+fn forgot(args: &Args) -> Action {
+    let name = args.word("name");
+    match (args.get("code"), args.get("new password")) {
+        (Some(code), Some(passwd)) => match Account::reset_password(name, code, passwd) {
+            Ok(()) => Noop(format!("Your password has been changed. You may now log in.\n")),
+            Err(e) => Noop(e.notice()),
+        },
+        _ => {
+            Account::request_reset(name);
+            Noop(format!(
+                "If '{}' has a verified email address, a reset code has been sent to it.\n\
+                 Enter 'forgot {} <code> <new password>' once you have received it.\n",
+                name, name
+            ))
+        }
+    }
 }
 
-/// Say goodbye to the player and disconnect them
-fn quit(_line: &mut SplitWhitespace) -> ConnAction {
+/// Say goodbye to the player and disconnect them, taking their character out of the world first
+fn quit(_args: &Args) -> Action {
     Disconnect
 }
 
 // TODO this should auto-login the player and change their state
 /// Attempt to register a new player account
-fn register(line: &mut SplitWhitespace) -> ConnAction {
-    if let Some(name) = line.next() {
-        if let Some(passwd) = line.next() {
-            match Account::new(name.to_string(), passwd.to_string()) {
-                Ok(acct) => return Login(acct, format!("Registered new user: {}\n", name)),
-                Err(e) => return Noop(e),
-            }
-        }
+fn register(args: &Args) -> Action {
+    let name = args.word("name");
+    match Account::new(name.to_string(), args.word("password").to_string()) {
+        Ok(acct) => Login(acct, format!("Registered new user: {}\n", name)),
+        Err(e) => Noop(e.notice()),
     }
-    Noop(format!("Registration Failed\n"))
 }
 
 /// Log a player into their account
-fn login(line: &mut SplitWhitespace) -> ConnAction {
-    if let Some(name) = line.next() {
-        if let Some(passwd) = line.next() {
-            match Account::login(name.to_string(), passwd.to_string()) {
-                Ok(acct) => return Login(acct, format!("Successfully logged in as {}\n", name)),
-                Err(e) => return Noop(e),
+fn login(args: &Args) -> Action {
+    let (name, passwd) = (args.word("name"), args.word("password"));
+    match Account::login(name.to_string(), passwd.to_string()) {
+        Ok(acct) => {
+            if acct.has_second_factor() {
+                return Challenge(
+                    acct,
+                    format!("Enter the code from your authenticator, or a recovery code: "),
+                );
             }
+            complete_login(acct)
         }
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+// Finish logging a player in once they have proven who they are
+fn complete_login(mut acct: Account) -> Action {
+    let mut msg = format!("Successfully logged in as {}\n", acct.name);
+    if acct.cancel_deletion() {
+        msg.push_str("Your account was due to be deleted. It has been restored.\n");
+    }
+    Login(acct, msg)
+}
+
+/// Check the second factor given by a player who has entered their password
+pub fn cmd_second_factor(mut acct: Account, input: String) -> Action {
+    match acct.check_second_factor(input.trim()) {
+        Ok(true) => {
+            info!("Successful second factor for: {}", acct.name);
+            complete_login(acct)
+        }
+        Ok(false) => {
+            warn!("Failed second factor for: {}", acct.name);
+            Noop(format!("Invalid code.\n"))
+        }
+        Err(e) => Noop(e.notice()),
     }
-    Noop(format!("Invalid login. Try again.\n"))
 }
 
 /// Parse commands for players in `Connected` state
-pub fn cmd_connected(input: String) -> ConnAction {
-    let mut line = input.split_whitespace();
-    if let Some(cmd) = line.next() {
-        let cmd_match: Vec<&str> = CONN_CMDS
-            .keys()
-            .filter(|k| k.starts_with(cmd))
-            .map(|&s| s)
-            .collect();
-        if cmd_match.is_empty() {
-            let reline = format!("{} {}", cmd, line.collect::<String>());
-            let mut reline = reline.split_whitespace();
-            login(&mut reline)
-        } else if cmd_match.len() > 1 {
-            Noop(format!(
-                "Ambiguous command: {:?}\nMatches:{:?}\n",
-                cmd, cmd_match
-            ))
-        } else {
-            let func = CONN_CMDS.get(cmd_match.first().unwrap()).unwrap();
-            func(&mut line)
+pub fn cmd_connected(input: String) -> Action {
+    // Anything that isn't a command is taken as a name and password to log in with
+    let login;
+    let mut line = &*input;
+    if let Some(cmd) = input.split_whitespace().next() {
+        if let Lookup::Unknown(_) = COMMANDS.find(Connected, Role::Player, cmd) {
+            login = format!("login {}", input);
+            line = &login;
         }
-    } else {
-        Noop("".to_string())
     }
+    dispatch(Context {
+        stage: Connected,
+        acct: None,
+        chr: None,
+        line,
+    })
 }
@@ -0,0 +1,43 @@
+use account::*;
+use character::*;
+use chargen::{Draft, Outcome};
+
+#[derive(Debug)]
+pub enum CreateAction {
+    Continue(String), // Stay in the wizard and display the message
+    Leave(String),    // Return to the lobby and display the message
+}
+
+/// Parse input for players in the character creation wizard. The draft is saved after every
+/// change, so the player may leave and come back to it at any time.
+pub fn cmd_creating(acct: &Account, draft: &mut Draft, input: String) -> CreateAction {
+    let line = input.trim();
+    match line {
+        "abort" => CreateAction::Leave(format!(
+            "Your progress has been saved. Use 'create' to continue where you left off.\n"
+        )),
+        "back" => {
+            draft.back();
+            draft.save();
+            CreateAction::Continue(draft.prompt())
+        }
+        _ => match draft.apply(line) {
+            Outcome::Next => {
+                draft.next();
+                draft.save();
+                CreateAction::Continue(draft.prompt())
+            }
+            Outcome::Stay(msg) => {
+                draft.save();
+                CreateAction::Continue(format!("{}{}", msg, draft.prompt()))
+            }
+            Outcome::Finish => match Character::new(acct, draft) {
+                Ok(chr) => {
+                    draft.discard();
+                    CreateAction::Leave(format!("Created new character: {}\n", chr.name))
+                }
+                Err(e) => CreateAction::Continue(format!("{}{}", e.notice(), draft.prompt())),
+            },
+        },
+    }
+}
@@ -0,0 +1,231 @@
+use super::args;
+use super::play::DIRECTION_HINT;
+use super::*;
+use error::Error;
+use help::{self, Topic};
+use shared::*;
+
+use self::Stage::*;
+
+// Add the help command, which is available everywhere, and the commands for editing help
+pub(super) fn add_commands(reg: &mut Registry) {
+    reg.add(Help);
+    reg.add(
+        Cmd::new(
+            "hedit",
+            &[Idle, Playing],
+            "Edit the help topics",
+            Handler::ForAccount(hedit),
+        ).role(Role::Admin)
+            .args(&[Param::word("topic"), Param::literal("add"), Param::rest("text")])
+            .args(&[Param::word("topic"), Param::literal("clear")])
+            .args(&[
+                Param::word("topic"),
+                Param::literal("keywords"),
+                Param::rest("words"),
+            ])
+            .args(&[
+                Param::word("topic"),
+                Param::literal("seealso"),
+                Param::rest("topics"),
+            ])
+            .args(&[Param::word("topic"), Param::literal("role"), Param::word("role")])
+            .args(&[Param::word("topic"), Param::literal("delete")])
+            .args(&[Param::word("topic")]),
+    );
+}
+
+/// Show the menu for where the player is, or help on a command or topic
+struct Help;
+
+impl Command for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["?"]
+    }
+
+    fn stages(&self) -> &[Stage] {
+        &[Connected, Idle, Playing]
+    }
+
+    fn help(&self) -> &'static str {
+        "Show help on a command or topic, or search for one"
+    }
+
+    fn usage(&self) -> Vec<String> {
+        vec![format!("help"), format!("help <command or topic>")]
+    }
+
+    fn execute(&self, ctx: &mut Context) -> Action {
+        let role = ctx.acct.as_ref().map(|a| a.role()).unwrap_or(Role::Player);
+        match ctx.line.split_whitespace().next() {
+            Some(word) => Noop(lookup(ctx.stage, role, word)),
+            None => Noop(menu(ctx.stage, role)),
+        }
+    }
+}
+
+// What there is to do where the player is
+fn menu(stage: Stage, role: Role) -> String {
+    match stage {
+        Connected => SPLASH.to_string(),
+        Idle if role == Role::Admin => format!("{}{}", ADMIN_LOBBY, LOBBY),
+        Idle => LOBBY.to_string(),
+        Playing => {
+            let mut cmds = COMMANDS.available(Playing, role);
+            cmds.retain(|c| !c.exact());
+            cmds.sort_by_key(|c| c.name());
+            let list: String = cmds
+                .iter()
+                .map(|c| format!("  {:<16} - {}\n", c.name(), c.help()))
+                .collect();
+            format!(
+                "Commands:\n{}{}Help topics: {}\nEnter 'help <command or topic>' for more.\n",
+                list,
+                DIRECTION_HINT,
+                help::names(role).join(", ")
+            )
+        }
+    }
+}
+
+// Find help on what the player asked about. A command's full name wins, then a topic's, then an
+// abbreviated command, and failing all those the topics are searched.
+fn lookup(stage: Stage, role: Role, word: &str) -> String {
+    let word = word.to_lowercase();
+    let cmd = match COMMANDS.find(stage, role, &word) {
+        Lookup::Found(cmd) => Some(cmd),
+        _ => None,
+    };
+    if let Some(cmd) = cmd.filter(|c| c.name() == word || c.aliases().contains(&&*word)) {
+        return show(Some(cmd), help::topic(cmd.name(), role).as_ref());
+    }
+    if let Some(topic) = help::topic(&word, role) {
+        return show(None, Some(&topic));
+    }
+    if let Some(cmd) = cmd {
+        return show(Some(cmd), help::topic(cmd.name(), role).as_ref());
+    }
+
+    let found = help::search(&word, role);
+    match found.len() {
+        0 => format!(
+            "There is no help on '{}'. Enter 'help' to see what there is.\n",
+            word
+        ),
+        1 => show(None, Some(&found[0])),
+        _ => {
+            let names: Vec<&str> = found.iter().map(|t| &*t.name).collect();
+            format!("Help topics matching '{}': {}\n", word, names.join(", "))
+        }
+    }
+}
+
+// Describe a command from what it says of itself, along with any topic written about it
+fn show(cmd: Option<&dyn Command>, topic: Option<&Topic>) -> String {
+    let mut text = String::new();
+    if let Some(cmd) = cmd {
+        text.push_str(&format!("Help on {}:\n", cmd.name()));
+        text.push_str(&args::usage_message(&cmd.usage()));
+        if !cmd.aliases().is_empty() {
+            text.push_str(&format!("Also entered as: {}\n", cmd.aliases().join(", ")));
+        }
+        if cmd.role() > Role::Player {
+            text.push_str(&format!("Only for the {} role and above.\n", cmd.role().name()));
+        }
+        text.push_str(&format!("{}.\n", cmd.help()));
+    }
+    if let Some(topic) = topic {
+        if cmd.is_none() {
+            text.push_str(&format!("Help on {}:\n", topic.name));
+        }
+        text.push_str(&topic.body);
+        if !topic.see_also.is_empty() {
+            text.push_str(&format!("See also: {}\n", topic.see_also.join(", ")));
+        }
+    }
+    text
+}
+
+/// Show a help topic as it's stored, or change it
+fn hedit(_acct: &mut Account, args: &Args) -> Action {
+    let name = args.word("topic");
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Noop(format!(
+            "Topic names may only contain letters, digits and hyphens.\n"
+        ));
+    }
+
+    let saved = if args.has("add") {
+        let line = args.word("text");
+        // Such a line would start a new topic when the help file is read back
+        if line.starts_with("== ") {
+            return Noop(format!("A line of help text can't start with '== '.\n"));
+        }
+        help::edit(name, |t| {
+            t.body.push_str(line);
+            t.body.push('\n');
+        })
+    } else if args.has("clear") {
+        help::edit(name, |t| t.body.clear())
+    } else if args.has("keywords") {
+        let words = args.word("words");
+        help::edit(name, |t| {
+            t.keywords = words.split_whitespace().map(|w| w.to_lowercase()).collect()
+        })
+    } else if args.has("seealso") {
+        let topics = args.word("topics");
+        help::edit(name, |t| {
+            t.see_also = topics
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect()
+        })
+    } else if args.has("role") {
+        match Role::parse(args.word("role")) {
+            Some(role) => help::edit(name, |t| t.role = role),
+            None => {
+                return Noop(format!(
+                    "'{}' isn't a role. Roles are player, builder and admin.\n",
+                    args.word("role")
+                ))
+            }
+        }
+    } else if args.has("delete") {
+        return match help::delete(name) {
+            Ok(true) => Noop(format!("Deleted the help topic '{}'.\n", name)),
+            Ok(false) => Noop(format!("There is no help topic '{}'.\n", name)),
+            Err(e) => {
+                error!("Could not save the help file: {}", e);
+                Noop(Error::Unavailable("Help editing").notice())
+            }
+        };
+    } else {
+        return Noop(match help::topic(name, Role::Admin) {
+            Some(t) => format!(
+                "== {}\nkeywords: {}\nsee also: {}\nrole: {}\n\n{}",
+                t.name,
+                t.keywords.join(" "),
+                t.see_also.join(", "),
+                t.role.name(),
+                t.body
+            ),
+            None => format!(
+                "There is no help topic '{}'. Use 'hedit {} add <text...>' to write one.\n",
+                name, name
+            ),
+        });
+    };
+
+    match saved {
+        Ok(()) => Noop(format!("Saved the help topic '{}'.\n", name)),
+        Err(e) => {
+            error!("Could not save the help file: {}", e);
+            Noop(Error::Unavailable("Help editing").notice())
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use super::*;
+use account::*;
+use ban::{self, Ban};
+use character::*;
+use chargen::Draft;
+use config::CONFIG;
+
+use self::Handler::ForAccount;
+use self::Stage::*;
+
+// Add the commands available in the character selection lobby
+pub(super) fn add_commands(reg: &mut Registry) {
+    reg.add(
+        Cmd::new(
+            "2fa",
+            &[Idle],
+            "Set up two-factor authentication",
+            ForAccount(two_factor),
+        ).args(&[])
+            .args(&[Param::literal("enroll"), Param::text("password")])
+            .args(&[Param::literal("confirm"), Param::word("code")])
+            .args(&[
+                Param::literal("disable"),
+                Param::text("password"),
+                Param::word("code"),
+            ])
+            .args(&[Param::literal("recovery"), Param::text("password")])
+            .secret(),
+    );
+    reg.add(
+        Cmd::new("ban", &[Idle], "Ban an account or address", ForAccount(ban))
+            .role(Role::Admin)
+            .args(&[
+                Param::literal("account"),
+                Param::word("name"),
+                Param::word("length"),
+                Param::rest("reason"),
+            ])
+            .args(&[
+                Param::literal("address"),
+                Param::word("ip or cidr"),
+                Param::word("length"),
+                Param::rest("reason"),
+            ])
+            .args(&[Param::literal("list")])
+            .args(&[Param::literal("lift"), Param::word("id")]),
+    );
+    reg.add(Cmd::new("create", &[Idle], "Create a new character", ForAccount(create)));
+    reg.add(
+        Cmd::new(
+            "delete",
+            &[Idle],
+            "Delete a character, or your whole account",
+            ForAccount(delete),
+        ).args(&[Param::literal("account"), Param::text("password")])
+            .args(&[Param::player("name")])
+            .secret(),
+    );
+    reg.add(
+        Cmd::new(
+            "email",
+            &[Idle],
+            "Show or change your email address",
+            ForAccount(email),
+        ).args(&[])
+            .args(&[Param::word("address"), Param::text("password")])
+            .secret(),
+    );
+    reg.add(Cmd::new("list", &[Idle], "List your characters", ForAccount(list)));
+    reg.add(Cmd::new("logout", &[Idle], "Return to the login menu", ForAccount(logout)));
+    reg.add(
+        Cmd::new("password", &[Idle], "Change your password", ForAccount(password))
+            .args(&[Param::text("current"), Param::text("new")])
+            .secret(),
+    );
+    reg.add(
+        Cmd::new(
+            "select",
+            &[Idle],
+            "Enter the world as a character",
+            ForAccount(select),
+        ).args(&[Param::player("name")]),
+    );
+    reg.add(
+        Cmd::new(
+            "verify",
+            &[Idle],
+            "Confirm your email address",
+            ForAccount(verify),
+        ).args(&[Param::word("code")]),
+    );
+}
+
+/// Ban an account or address, list the bans in effect, or lift one
+fn ban(acct: &mut Account, args: &Args) -> Action {
+    if args.has("list") {
+        return match Ban::list() {
+            Ok(ref bans) if bans.is_empty() => Noop(format!("There are no bans in effect.\n")),
+            Ok(bans) => Noop(
+                bans.iter()
+                    .map(|b| format!("  {}\n", b.summary()))
+                    .collect(),
+            ),
+            Err(e) => Noop(e.notice()),
+        };
+    }
+    if args.has("lift") {
+        let id = args.word("id");
+        return match id.trim_start_matches('#').parse() {
+            Ok(id) => match Ban::lift(id) {
+                Ok(()) => Noop(format!("Lifted ban #{}.\n", id)),
+                Err(e) => Noop(e.notice()),
+            },
+            Err(_) => Noop(format!("'{}' isn't the number of a ban.\n", id)),
+        };
+    }
+
+    let length = match ban::parse_duration(args.word("length")) {
+        Some(length) => length,
+        None => {
+            return Noop(format!(
+                "'{}' isn't a length. Lengths are given like 30m, 12h, 7d or 2w, or 'perm' for a \
+                 ban that never expires.\n",
+                args.word("length")
+            ))
+        }
+    };
+    let reason = args.word("reason");
+    let issued = if args.has("account") {
+        Ban::account(args.word("name"), &acct.name, reason, length)
+    } else {
+        Ban::address(args.word("ip or cidr"), &acct.name, reason, length)
+    };
+    match issued {
+        Ok(ban) => Noop(format!("Issued ban {}\n", ban.summary())),
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// Start the character creation wizard, or pick up an unfinished character where it was left
+fn create(acct: &mut Account, _args: &Args) -> Action {
+    match Draft::resume(acct) {
+        Ok(draft) => Create(draft),
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// Delete one of the player's characters, or their whole account
+fn delete(acct: &mut Account, args: &Args) -> Action {
+    if args.has("account") {
+        match acct.schedule_deletion(args.word("password")) {
+            Ok(()) => Logout(format!(
+                "Your account will be deleted in {} days, along with all of your characters.\n\
+                 Log in again before then if you change your mind.\n",
+                CONFIG.security.deletion_grace_days
+            )),
+            Err(e) => Noop(e.notice()),
+        }
+    } else {
+        let name = args.word("name");
+        match Character::delete(acct, name) {
+            Ok(()) => Noop(format!("Deleted character: {}\n", name)),
+            Err(e) => Noop(e.notice()),
+        }
+    }
+}
+
+/// Show the player's email address, or change it and send a verification code to the new one
+fn email(acct: &mut Account, args: &Args) -> Action {
+    if let Some(address) = args.get("address") {
+        if !acct.check_password(args.word("password")) {
+            return Noop(format!("Incorrect password.\n"));
+        }
+        match acct.set_email(address) {
+            Ok(()) => Noop(format!("Sending a verification code to {}...\n", address)),
+            Err(e) => Noop(e.notice()),
+        }
+    } else {
+        match acct.email() {
+            Some((address, true)) => Noop(format!("Your email address is {}.\n", address)),
+            Some((address, false)) => Noop(format!(
+                "Your email address is {}, but it has not been verified yet.\n",
+                address
+            )),
+            None => Noop(format!(
+                "You have not set an email address. Usage: email <address> <password>\n"
+            )),
+        }
+    }
+}
+
+/// List the player's characters
+fn list(acct: &mut Account, _args: &Args) -> Action {
+    match Character::list(acct) {
+        Ok(ref chars) if chars.is_empty() => Noop(format!(
+            "You have no characters yet. Use 'create' to make one.\n"
+        )),
+        Ok(chars) => {
+            let namelist: String = chars
+                .iter()
+                .map(|c| format!("  {:<16} {} {}\n", c.name, c.race, c.class))
+                .collect();
+            Noop(format!(
+                "Your characters ({}/{}):\n{}",
+                chars.len(),
+                CONFIG.game.max_characters,
+                namelist
+            ))
+        }
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// Log out and return to the login menu
+fn logout(_acct: &mut Account, _args: &Args) -> Action {
+    Logout(format!("You have logged out.\n"))
+}
+
+/// Change the player's password
+fn password(acct: &mut Account, args: &Args) -> Action {
+    match acct.change_password(args.word("current"), args.word("new")) {
+        Ok(()) => Noop(format!("Your password has been changed.\n")),
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// Enter the world as one of the player's characters
+fn select(acct: &mut Account, args: &Args) -> Action {
+    if acct.missing_second_factor() {
+        return Noop(format!(
+            "Accounts with the {} role must set up two-factor authentication before entering \
+             the world.\nUse '2fa enroll <password>' to begin.\n",
+            acct.role().name()
+        ));
+    }
+    match Character::select(acct, args.word("name")) {
+        Ok(chr) => {
+            let msg = format!("You enter the world as {}.\n", chr.name);
+            Play(chr, msg)
+        }
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// Set up, confirm or turn off two-factor authentication, or replace the recovery codes
+fn two_factor(acct: &mut Account, args: &Args) -> Action {
+    let codes = |codes: Vec<String>| -> String {
+        let list: String = codes.iter().map(|c| format!("  {}\n", c)).collect();
+        format!(
+            "Your recovery codes are listed below. Each may be used once in place of a code from \
+             your authenticator.\nKeep them somewhere safe, they will not be shown again.\n{}",
+            list
+        )
+    };
+    let (passwd, code) = (args.word("password"), args.word("code"));
+    if args.has("enroll") {
+        match acct.enroll_totp(passwd) {
+            Ok(uri) => Noop(format!(
+                "Add this account to your authenticator app using the URI below, then enter\n\
+                 '2fa confirm <code>' with the code it shows.\n  {}\n",
+                uri
+            )),
+            Err(e) => Noop(e.notice()),
+        }
+    } else if args.has("confirm") {
+        match acct.confirm_totp(code) {
+            Ok(list) => Noop(format!(
+                "Two-factor authentication is now enabled.\n{}",
+                codes(list)
+            )),
+            Err(e) => Noop(e.notice()),
+        }
+    } else if args.has("disable") {
+        match acct.disable_totp(passwd, code) {
+            Ok(()) => Noop(format!("Two-factor authentication is now disabled.\n")),
+            Err(e) => Noop(e.notice()),
+        }
+    } else if args.has("recovery") {
+        match acct.regenerate_recovery_codes(passwd) {
+            Ok(list) => Noop(codes(list)),
+            Err(e) => Noop(e.notice()),
+        }
+    } else {
+        Noop(format!(
+            "Two-factor authentication is {}.\n{}",
+            if acct.has_second_factor() {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            usage_of(Idle, "2fa")
+        ))
+    }
+}
+
+/// Confirm the player's email address with the code that was mailed to them
+fn verify(acct: &mut Account, args: &Args) -> Action {
+    match acct.verify_email(args.word("code")) {
+        Ok(()) => Noop(format!("Your email address has been verified.\n")),
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// Parse commands for players in `Idle` state
+pub fn cmd_idle(acct: &mut Account, input: String) -> Action {
+    dispatch(Context {
+        stage: Idle,
+        acct: Some(acct),
+        chr: None,
+        line: &input,
+    })
+}
@@ -1,5 +1,377 @@
+use account::{Account, Role};
+use character::Character;
+use chargen::Draft;
+
+pub use self::args::{Args, Param};
 pub use self::conn::*;
+pub use self::create::*;
+pub use self::idle::*;
 pub use self::play::*;
 
+mod args;
+mod chat;
+mod comm;
 mod conn;
+mod create;
+mod help;
+mod idle;
 mod play;
+mod session;
+
+/// What a command asks of the player's session once it has run
+#[derive(Debug)]
+pub enum Action {
+    Disconnect,
+    Challenge(Account, String), // The password was right, but a second factor is needed
+    Login(Account, String),
+    Logout(String),          // Return from the lobby to the login menu
+    Create(Draft),           // Start or resume the character creation wizard
+    Play(Character, String), // Enter the world as the character
+    Leave(String),           // Take the character out of the world and return to the lobby
+    Noop(String),            // Stay as we are and display the message
+    ShowHistory,             // List the commands the player entered recently
+    ShowQueue,               // List the commands waiting their turn to run
+    ClearQueue,              // Drop every command waiting its turn
+}
+
+pub use self::Action::*;
+
+/// The stages of a session in which commands are available
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Connected, // At the login menu
+    Idle,      // In the character selection lobby
+    Playing,   // In the world as a character
+}
+
+/// Everything a command may reach while it runs
+pub struct Context<'a> {
+    pub stage: Stage,
+    pub acct: Option<&'a mut Account>,  // The player's account, once they have logged in
+    pub chr: Option<&'a mut Character>, // The character they play, while they're in the world
+    pub line: &'a str,                  // What the player entered after the command
+}
+
+/// A command players can enter
+pub trait Command: Send + Sync {
+    /// The command's full name
+    fn name(&self) -> &'static str;
+
+    /// Other names the command can be entered by
+    fn aliases(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// The stages in which the command is available
+    fn stages(&self) -> &[Stage];
+
+    /// The least privileged role that may use the command
+    fn role(&self) -> Role {
+        Role::Player
+    }
+
+    /// One line describing what the command does
+    fn help(&self) -> &'static str;
+
+    /// The ways the command can be entered, as in "login <name> <password>"
+    fn usage(&self) -> Vec<String> {
+        vec![self.name().to_string()]
+    }
+
+    /// Which command wins when an abbreviation matches several. Higher wins.
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// Whether the command must be entered in full, by name or alias, rather than abbreviated
+    fn exact(&self) -> bool {
+        false
+    }
+
+    /// Whether the command takes a password or the like, so must never be kept in a history
+    fn secret(&self) -> bool {
+        false
+    }
+
+    fn execute(&self, ctx: &mut Context) -> Action;
+}
+
+/// The function behind a `Cmd`, by what it needs from the session
+#[derive(Clone, Copy)]
+pub enum Handler {
+    ForGuest(fn(&Args) -> Action),                    // Only the command's arguments
+    ForAccount(fn(&mut Account, &Args) -> Action),     // The player's account
+    ForCharacter(fn(&mut Character, &Args) -> Action), // The character they play
+}
+
+/// A command that runs a plain function, described where it's registered
+pub struct Cmd {
+    name: &'static str,
+    aliases: Vec<&'static str>,
+    forms: Vec<Vec<Param>>, // The arguments it takes, in each of the ways it can be entered
+    stages: Vec<Stage>,
+    role: Role,
+    help: &'static str,
+    priority: i32,
+    secret: bool,
+    handler: Handler,
+}
+
+impl Cmd {
+    pub fn new(name: &'static str, stages: &[Stage], help: &'static str, handler: Handler) -> Cmd {
+        Cmd {
+            name,
+            aliases: Vec::new(),
+            forms: Vec::new(),
+            stages: stages.to_vec(),
+            role: Role::Player,
+            help,
+            priority: 0,
+            secret: false,
+            handler,
+        }
+    }
+
+    pub fn alias(mut self, alias: &'static str) -> Cmd {
+        self.aliases.push(alias);
+        self
+    }
+
+    /// Add a way of entering the command. Commands take no arguments until given one.
+    pub fn args(mut self, form: &[Param]) -> Cmd {
+        self.forms.push(form.to_vec());
+        self
+    }
+
+    // The ways of entering the command, which is just its name if it takes no arguments
+    fn forms(&self) -> Vec<Vec<Param>> {
+        if self.forms.is_empty() {
+            vec![Vec::new()]
+        } else {
+            self.forms.clone()
+        }
+    }
+
+    pub fn role(mut self, role: Role) -> Cmd {
+        self.role = role;
+        self
+    }
+
+    pub fn priority(mut self, priority: i32) -> Cmd {
+        self.priority = priority;
+        self
+    }
+
+    pub fn secret(mut self) -> Cmd {
+        self.secret = true;
+        self
+    }
+}
+
+impl Command for Cmd {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &self.aliases
+    }
+
+    fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    fn role(&self) -> Role {
+        self.role
+    }
+
+    fn help(&self) -> &'static str {
+        self.help
+    }
+
+    fn usage(&self) -> Vec<String> {
+        self.forms().iter().map(|f| args::usage(self.name, f)).collect()
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn secret(&self) -> bool {
+        self.secret
+    }
+
+    fn execute(&self, ctx: &mut Context) -> Action {
+        let args = match args::parse(self.name, &self.forms(), ctx.line) {
+            Ok(args) => args,
+            Err(usage) => return Noop(usage),
+        };
+        match (self.handler, ctx.acct.as_mut(), ctx.chr.as_mut()) {
+            (Handler::ForGuest(f), _, _) => f(&args),
+            (Handler::ForAccount(f), Some(acct), _) => f(acct, &args),
+            (Handler::ForCharacter(f), _, Some(chr)) => f(chr, &args),
+            // The registry only offers commands in the stages they were registered for
+            _ => Noop(format!("You can't do that right now.\n")),
+        }
+    }
+}
+
+/// The outcome of looking up what a player entered
+pub enum Lookup<'a> {
+    Found(&'a dyn Command),
+    Ambiguous(Vec<&'static str>), // Several commands match equally well
+    Unknown(Vec<&'static str>),   // Nothing matches, but these must be entered in full and begin so
+}
+
+/// Every command, whatever stage it's available in
+pub struct Registry {
+    commands: Vec<Box<dyn Command>>,
+}
+
+lazy_static! {
+    pub static ref COMMANDS: Registry = {
+        let mut reg = Registry {
+            commands: Vec::new(),
+        };
+        chat::add_commands(&mut reg);
+        comm::add_commands(&mut reg);
+        conn::add_commands(&mut reg);
+        help::add_commands(&mut reg);
+        idle::add_commands(&mut reg);
+        play::add_commands(&mut reg);
+        session::add_commands(&mut reg);
+        reg
+    };
+}
+
+impl Registry {
+    pub fn add<C: Command + 'static>(&mut self, cmd: C) {
+        self.commands.push(Box::new(cmd));
+    }
+
+    /// The commands a player with the given role may use in a stage
+    pub fn available(&self, stage: Stage, role: Role) -> Vec<&dyn Command> {
+        self.commands
+            .iter()
+            .map(|c| &**c)
+            .filter(|c| c.stages().contains(&stage) && c.role() <= role)
+            .collect()
+    }
+
+    /// Find the command a player meant. A full name or alias wins outright. Otherwise the
+    /// abbreviation must pick out a single command, or a single one of the highest priority.
+    pub fn find(&self, stage: Stage, role: Role, word: &str) -> Lookup<'_> {
+        let cmds = self.available(stage, role);
+        let named = |c: &&&dyn Command| c.name() == word || c.aliases().contains(&word);
+        if let Some(cmd) = cmds.iter().filter(named).max_by_key(|c| c.priority()) {
+            return Lookup::Found(*cmd);
+        }
+
+        let abbreviates = |c: &&&dyn Command| {
+            !c.exact()
+                && (c.name().starts_with(word) || c.aliases().iter().any(|a| a.starts_with(word)))
+        };
+        let top = match cmds.iter().filter(abbreviates).map(|c| c.priority()).max() {
+            Some(top) => top,
+            None => {
+                let close = cmds
+                    .iter()
+                    .filter(|c| c.exact() && c.name().starts_with(word))
+                    .map(|c| c.name())
+                    .collect();
+                return Lookup::Unknown(close);
+            }
+        };
+        let best: Vec<&dyn Command> = cmds
+            .iter()
+            .filter(abbreviates)
+            .filter(|c| c.priority() == top)
+            .cloned()
+            .collect();
+        if best.len() == 1 {
+            Lookup::Found(best[0])
+        } else {
+            Lookup::Ambiguous(best.iter().map(|c| c.name()).collect())
+        }
+    }
+
+    /// Every name and alias of every command
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.commands
+            .iter()
+            .flat_map(|c| {
+                let mut n = vec![c.name()];
+                n.extend(c.aliases());
+                n
+            })
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// Run the command at the start of the player's input, telling them if there isn't one
+pub fn dispatch(mut ctx: Context) -> Action {
+    let input = ctx.line.trim_start();
+    let (cmd, rest) = match input.find(char::is_whitespace) {
+        Some(end) => (&input[..end], &input[end..]),
+        None => (input, ""),
+    };
+    if cmd.is_empty() {
+        return Noop(String::new());
+    }
+    ctx.line = rest;
+    let role = ctx.acct.as_ref().map(|a| a.role()).unwrap_or(Role::Player);
+    match COMMANDS.find(ctx.stage, role, cmd) {
+        Lookup::Found(command) => command.execute(&mut ctx),
+        Lookup::Ambiguous(matches) => Noop(format!(
+            "Ambiguous command: {:?}\nMatches:{:?}\n",
+            cmd, matches
+        )),
+        Lookup::Unknown(ref close) if close.is_empty() => {
+            Noop(format!("Unrecognized command: '{}'\n", cmd))
+        }
+        Lookup::Unknown(close) => Noop(format!(
+            "Unrecognized command: '{}'. Did you mean {}?\n",
+            cmd,
+            close.join(", ")
+        )),
+    }
+}
+
+/// Whether the line runs a command that must never be kept in a history. In the lobby, a line
+/// that runs no command at all may be a mistyped "password" and is kept out too. In the world
+/// it may be an alias, and those are never secret.
+pub fn is_secret(stage: Stage, role: Role, line: &str) -> bool {
+    let word = line.split_whitespace().next().unwrap_or("");
+    match COMMANDS.find(stage, role, word) {
+        Lookup::Found(cmd) => cmd.secret(),
+        _ => stage == Stage::Idle,
+    }
+}
+
+/// The "Usage:" message for a command, from the grammar it was registered with
+pub fn usage_of(stage: Stage, name: &str) -> String {
+    let lines: Vec<String> = COMMANDS
+        .available(stage, Role::Admin)
+        .iter()
+        .filter(|c| c.name() == name)
+        .flat_map(|c| c.usage())
+        .collect();
+    args::usage_message(&lines)
+}
+
+/// Whether the word would be taken for a command at the login menu, in full or abbreviated,
+/// rather than as a name to log in with
+pub fn is_login_command(word: &str) -> bool {
+    match COMMANDS.find(Stage::Connected, Role::Player, word) {
+        Lookup::Unknown(_) => false,
+        _ => true,
+    }
+}
+
+/// Every command recognized in any state, so that players cannot take them as names
+pub fn command_names() -> Vec<&'static str> {
+    COMMANDS.names()
+}
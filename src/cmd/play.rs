@@ -1,98 +1,292 @@
-use diesel::prelude::*;
-
 use std::collections::BTreeMap;
-use std::iter::Iterator;
-use std::str::SplitWhitespace;
 
-use account::*;
-use shared::*;
+use super::*;
+use account::Account;
+use alias;
+use character::{Character, DEFAULT_PROMPT};
+use store::STORE;
+use world::{self, Direction, Side};
 
-type PlayFn = fn(&mut SplitWhitespace) -> PlayAction;
+use self::Handler::ForCharacter;
+use self::Stage::*;
 
-#[derive(Debug)]
-pub enum PlayAction {
-    Move,
-    Quit,
-    Noop(String),
+/// Where a movement command leads
+#[derive(Debug, Clone, Copy)]
+pub enum Way {
+    Toward(Direction), // Through the exit in a fixed direction
+    Relative(Side),    // Relative to the way the character is facing
 }
 
-pub use self::PlayAction::*;
+pub use self::Way::*;
+
+// Reminder of the directions, for players who give one that isn't, and for the help menu
+pub(super) static DIRECTION_HINT: &str = "Directions are north, northeast, east, southeast, south, \
+                               southwest, west, northwest, up, down, in and out,\n\
+                               or their short forms such as n, ne and u.\n";
 
-/// Tables of recognized commands during play
+/// Words that name a way to go, as commands on their own or after `go`
 lazy_static! {
-    static ref DIRECTIONS: BTreeMap<&'static str, PlayFn> = {
+    pub static ref DIRECTIONS: BTreeMap<&'static str, Way> = {
         let mut m = BTreeMap::new();
-        m.insert("north", go as PlayFn);
-        m.insert("n", go as PlayFn);
-        m.insert("northeast", go as PlayFn);
-        m.insert("ne", go as PlayFn);
-        m.insert("east", go as PlayFn);
-        m.insert("e", go as PlayFn);
-        m.insert("southeast", go as PlayFn);
-        m.insert("se", go as PlayFn);
-        m.insert("south", go as PlayFn);
-        m.insert("s", go as PlayFn);
-        m.insert("southwest", go as PlayFn);
-        m.insert("sw", go as PlayFn);
-        m.insert("west", go as PlayFn);
-        m.insert("w", go as PlayFn);
-        m.insert("northwest", go as PlayFn);
-        m.insert("nw", go as PlayFn);
-        m.insert("up", go as PlayFn);
-        m.insert("u", go as PlayFn);
-        m.insert("down", go as PlayFn);
-        m.insert("d", go as PlayFn);
-        m.insert("in", go as PlayFn);
-        m.insert("out", go as PlayFn);
-        m.insert("left", go as PlayFn);
-        m.insert("l", go as PlayFn);
-        m.insert("right", go as PlayFn);
-        m.insert("r", go as PlayFn);
-        m.insert("forward", go as PlayFn);
-        m.insert("f", go as PlayFn);
-        m.insert("backward", go as PlayFn);
-        m.insert("back", go as PlayFn);
-        m.insert("b", go as PlayFn);
-        m
-    };
-    static ref PLAY_CMDS: BTreeMap<&'static str, PlayFn> = {
-        let mut m = BTreeMap::new();
-        m.insert("quit", quit as PlayFn);
-        m.insert("logout", quit as PlayFn);
-        //m.insert("stats", stats as CmdFn);
-        //m.insert("who", who as CmdFn);
+        m.insert("north", Toward(Direction::North));
+        m.insert("n", Toward(Direction::North));
+        m.insert("northeast", Toward(Direction::Northeast));
+        m.insert("ne", Toward(Direction::Northeast));
+        m.insert("east", Toward(Direction::East));
+        m.insert("e", Toward(Direction::East));
+        m.insert("southeast", Toward(Direction::Southeast));
+        m.insert("se", Toward(Direction::Southeast));
+        m.insert("south", Toward(Direction::South));
+        m.insert("s", Toward(Direction::South));
+        m.insert("southwest", Toward(Direction::Southwest));
+        m.insert("sw", Toward(Direction::Southwest));
+        m.insert("west", Toward(Direction::West));
+        m.insert("w", Toward(Direction::West));
+        m.insert("northwest", Toward(Direction::Northwest));
+        m.insert("nw", Toward(Direction::Northwest));
+        m.insert("up", Toward(Direction::Up));
+        m.insert("u", Toward(Direction::Up));
+        m.insert("down", Toward(Direction::Down));
+        m.insert("d", Toward(Direction::Down));
+        m.insert("in", Toward(Direction::In));
+        m.insert("out", Toward(Direction::Out));
+        m.insert("left", Relative(Side::Left));
+        m.insert("l", Relative(Side::Left));
+        m.insert("right", Relative(Side::Right));
+        m.insert("r", Relative(Side::Right));
+        m.insert("forward", Relative(Side::Forward));
+        m.insert("f", Relative(Side::Forward));
+        m.insert("backward", Relative(Side::Back));
+        m.insert("back", Relative(Side::Back));
+        m.insert("b", Relative(Side::Back));
         m
     };
 }
 
-fn go(_line: &mut SplitWhitespace) -> PlayAction {
-    unimplemented!();
+// Add the commands available in the world
+pub(super) fn add_commands(reg: &mut Registry) {
+    for (&word, &way) in DIRECTIONS.iter() {
+        reg.add(Walk { word, way });
+    }
+    reg.add(
+        Cmd::new(
+            "alias",
+            &[Playing],
+            "List, show or define your aliases",
+            ForCharacter(define_alias),
+        ).args(&[])
+            .args(&[Param::word("name")])
+            .args(&[Param::word("name"), Param::rest("commands")]),
+    );
+    reg.add(
+        Cmd::new(
+            "go",
+            &[Playing],
+            "Go through the exit in the given direction",
+            ForCharacter(go),
+        ).args(&[Param::word("direction")]),
+    );
+    // Ahead of logout, so that "lo" looks around rather than leaving the world
+    reg.add(
+        Cmd::new("look", &[Playing], "Describe your surroundings", ForCharacter(look)).priority(1),
+    );
+    reg.add(
+        Cmd::new("map", &[Playing], "Draw a map of the rooms nearby", ForCharacter(map))
+            .args(&[Param::number("radius").optional()]),
+    );
+    reg.add(
+        Cmd::new(
+            "view",
+            &[Playing],
+            "Show exits and maps by the compass or by the way you face",
+            ForCharacter(view),
+        ).args(&[Param::literal("relative")])
+            .args(&[Param::literal("compass")]),
+    );
+    reg.add(
+        Cmd::new("prompt", &[Playing], "Show or change your prompt", ForCharacter(prompt))
+            .args(&[])
+            .args(&[Param::literal("default")])
+            .args(&[Param::rest("prompt")]),
+    );
+    reg.add(
+        Cmd::new("unalias", &[Playing], "Remove an alias", ForCharacter(unalias))
+            .args(&[Param::word("name")]),
+    );
+    reg.add(Cmd::new(
+        "logout",
+        &[Playing],
+        "Leave the world and return to character selection",
+        ForCharacter(logout),
+    ));
 }
 
-fn quit(_line: &mut SplitWhitespace) -> PlayAction {
-    unimplemented!();
+/// A direction entered on its own, as a command to go that way. Directions must be entered in
+/// full, by name or short form, since so many of them begin with the same letters.
+struct Walk {
+    word: &'static str,
+    way: Way,
 }
 
-pub fn cmd_playing(input: String) -> PlayAction {
-    let mut line = input.split_whitespace();
-    if let Some(cmd) = line.next() {
-        let cmd_match: Vec<&str> = PLAY_CMDS
-            .keys()
-            .filter(|k| k.starts_with(cmd))
-            .map(|&s| s)
-            .collect();
-        if cmd_match.is_empty() {
-            Noop(format!("Unrecognized command: '{}'", cmd))
-        } else if cmd_match.len() > 1 {
-            Noop(format!(
-                "Ambiguous command: {:?}\nMatches:{:?}\n",
-                cmd, cmd_match
+impl Command for Walk {
+    fn name(&self) -> &'static str {
+        self.word
+    }
+
+    fn stages(&self) -> &[Stage] {
+        &[Playing]
+    }
+
+    fn help(&self) -> &'static str {
+        match self.way {
+            Toward(_) => "Go through the exit in that direction",
+            Relative(_) => "Go that way from where you face",
+        }
+    }
+
+    fn exact(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, ctx: &mut Context) -> Action {
+        match ctx.chr {
+            Some(ref mut chr) => Noop(walk(chr, self.way)),
+            None => Noop(format!("You can't do that right now.\n")),
+        }
+    }
+}
+
+// Move the character the given way, returning what they see
+fn walk(chr: &mut Character, way: Way) -> String {
+    let dir = match way {
+        Toward(dir) => dir,
+        Relative(side) => world::facing(chr).turn(side),
+    };
+    world::travel(chr, dir)
+}
+
+/// List the character's aliases, show one, or define one
+fn define_alias(chr: &mut Character, args: &Args) -> Action {
+    let name = args.word("name");
+    if let Some(commands) = args.get("commands") {
+        return match alias::set(&chr.name, name, commands) {
+            Ok(()) => Noop(format!("'{}' now runs: {}\n", name.to_lowercase(), commands)),
+            Err(e) => Noop(e.notice()),
+        };
+    }
+    if args.has("name") {
+        return match alias::get(&chr.name, name) {
+            Ok(Some(commands)) => Noop(format!("'{}' runs: {}\n", name.to_lowercase(), commands)),
+            Ok(None) => Noop(format!("You have no alias '{}'.\n", name)),
+            Err(e) => Noop(e.notice()),
+        };
+    }
+    match alias::list(&chr.name) {
+        Ok(ref list) if list.is_empty() => Noop(format!(
+            "You have no aliases. Define one with 'alias <name> <commands...>', using ';' \
+             between commands\nand $1 to $9 or $* for what follows the alias.\n"
+        )),
+        Ok(list) => Noop(
+            list.iter()
+                .map(|&(ref name, ref commands)| format!("  {:<16} {}\n", name, commands))
+                .collect(),
+        ),
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// Show the character's prompt, or change it
+fn prompt(chr: &mut Character, args: &Args) -> Action {
+    let prompt = match args.get("prompt") {
+        // Quotes keep any spaces at the ends. Otherwise a space separates the prompt from what
+        // the player types next.
+        Some(p) if p.len() >= 2 && p.starts_with('"') && p.ends_with('"') => {
+            p[1..p.len() - 1].to_string()
+        }
+        Some(p) => format!("{} ", p),
+        None if args.has("default") => DEFAULT_PROMPT.to_string(),
+        None => {
+            return Noop(format!(
+                "Your prompt is \"{}\". Change it with 'prompt <text...>', using\n\
+                 %h and %H for your hit points and most hit points, %m and %M for mana,\n\
+                 %n for your name, %r for the room you're in, %f for the way you face,\n\
+                 and %% for a percent sign. 'prompt default' sets it back to \"{}\".\n",
+                chr.prompt, DEFAULT_PROMPT
             ))
-        } else {
-            let func = PLAY_CMDS.get(cmd_match.first().unwrap()).unwrap();
-            func(&mut line)
         }
-    } else {
-        Noop("".to_string())
+    };
+    chr.prompt = prompt;
+    if let Err(e) = STORE.update_character(chr) {
+        error!("Database error saving the prompt of {}: {}", chr.name, e);
+    }
+    Noop(format!("Your prompt is now \"{}\".\n", chr.prompt))
+}
+
+/// Remove one of the character's aliases
+fn unalias(chr: &mut Character, args: &Args) -> Action {
+    let name = args.word("name");
+    match alias::remove(&chr.name, name) {
+        Ok(true) => Noop(format!("Removed the alias '{}'.\n", name.to_lowercase())),
+        Ok(false) => Noop(format!("You have no alias '{}'.\n", name)),
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// Move through the exit in the given direction
+fn go(chr: &mut Character, args: &Args) -> Action {
+    let word = args.word("direction");
+    match DIRECTIONS.get(word) {
+        Some(&way) => Noop(walk(chr, way)),
+        None => Noop(format!("'{}' is not a direction. {}", word, DIRECTION_HINT)),
+    }
+}
+
+/// Describe the character's surroundings
+fn look(chr: &mut Character, _args: &Args) -> Action {
+    Noop(world::look(chr))
+}
+
+/// Draw a map of the rooms nearby
+fn map(chr: &mut Character, args: &Args) -> Action {
+    match args.number("radius").unwrap_or(world::MAP_RADIUS as i64) {
+        r if r >= 1 && r <= world::MAX_MAP_RADIUS as i64 => Noop(world::map(chr, r as i32)),
+        _ => Noop(format!(
+            "The map can reach between 1 and {} rooms out.\n",
+            world::MAX_MAP_RADIUS
+        )),
+    }
+}
+
+/// Choose whether exits and maps are shown by the compass or by the way the character faces
+fn view(chr: &mut Character, args: &Args) -> Action {
+    Noop(world::view(chr, args.has("relative")))
+}
+
+/// Leave the world and return to character selection
+fn logout(_chr: &mut Character, _args: &Args) -> Action {
+    Leave(format!("You leave the world.\n"))
+}
+
+/// Parse commands for players in the world. Returns what the commands an alias stands for
+/// displayed before the last one that ran, along with what that last one does.
+pub fn cmd_playing(acct: &mut Account, chr: &mut Character, input: String) -> (String, Action) {
+    let lines = match alias::expand(&chr.name, &input) {
+        Ok(lines) => lines,
+        Err(e) => return (String::new(), Noop(e.notice())),
+    };
+    // Run each command in turn, until one needs more than a message shown
+    let mut output = String::new();
+    for line in lines {
+        let action = dispatch(Context {
+            stage: Playing,
+            acct: Some(&mut *acct),
+            chr: Some(&mut *chr),
+            line: &line,
+        });
+        match action {
+            Noop(s) => output.push_str(&s),
+            action => return (output, action),
+        }
     }
+    (String::new(), Noop(output))
 }
@@ -0,0 +1,84 @@
+use super::*;
+use account::Account;
+
+use self::Handler::{ForAccount, ForGuest};
+use self::Stage::*;
+
+// Add the commands about the player's own session: what they've entered and what's waiting to run
+pub(super) fn add_commands(reg: &mut Registry) {
+    reg.add(Cmd::new(
+        "clear",
+        &[Idle, Playing],
+        "Cancel the commands waiting their turn",
+        ForGuest(clear),
+    ));
+    reg.add(Cmd::new(
+        "history",
+        &[Idle, Playing],
+        "List the commands you entered recently",
+        ForGuest(history),
+    ));
+    reg.add(
+        Cmd::new(
+            "pagesize",
+            &[Idle, Playing],
+            "Show or choose how many lines of output make a page",
+            ForAccount(page_size),
+        ).args(&[])
+            .args(&[Param::literal("auto")])
+            .args(&[Param::literal("off")])
+            .args(&[Param::number("lines")]),
+    );
+    reg.add(Cmd::new(
+        "queue",
+        &[Idle, Playing],
+        "List the commands waiting their turn",
+        ForGuest(queue),
+    ));
+}
+
+/// Cancel the commands waiting their turn
+fn clear(_args: &Args) -> Action {
+    ClearQueue
+}
+
+/// List the commands the player entered recently
+fn history(_args: &Args) -> Action {
+    ShowHistory
+}
+
+/// Show or choose how many lines of output make a page
+fn page_size(acct: &mut Account, args: &Args) -> Action {
+    let lines = if args.has("auto") {
+        None
+    } else if args.has("off") {
+        Some(0)
+    } else {
+        match args.number("lines") {
+            Some(n) if n >= 5 && n <= 500 => Some(n as usize),
+            Some(_) => return Noop(format!("Pages must be between 5 and 500 lines long.\n")),
+            None => {
+                return Noop(match acct.page_length() {
+                    None => format!(
+                        "Pages are as long as your screen, if your client says how long that is.\n"
+                    ),
+                    Some(0) => format!("Long output is not split into pages.\n"),
+                    Some(n) => format!("Pages are {} lines long.\n", n),
+                })
+            }
+        }
+    };
+    match acct.set_page_length(lines) {
+        Ok(()) => match lines {
+            None => Noop(format!("Pages are now as long as your screen.\n")),
+            Some(0) => Noop(format!("Long output is no longer split into pages.\n")),
+            Some(n) => Noop(format!("Pages are now {} lines long.\n", n)),
+        },
+        Err(e) => Noop(e.notice()),
+    }
+}
+
+/// List the commands waiting their turn
+fn queue(_args: &Args) -> Action {
+    ShowQueue
+}
@@ -0,0 +1,53 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// Shorthand for results that fail with the server's `Error`
+pub type Result<T> = result::Result<T, Error>;
+
+/// Everything that can go wrong while serving players. Only `Refused` carries a message meant for
+/// the player. The rest are described for the server log, and the player is only told that what
+/// they tried is unavailable for now.
+#[derive(Debug)]
+pub enum Error {
+    Refused(String),           // The request was turned down, with the reason to give the player
+    Unavailable(&'static str), // What the player tried, when a logged failure got in the way
+    Store(String),             // The storage backend failed
+    Mail(String),              // Mail could not be handed to the transport
+    Hash(String),              // A password could not be hashed
+    Io(io::Error),             // A player's connection failed
+}
+
+impl Error {
+    /// What to tell the player about the error
+    pub fn notice(&self) -> String {
+        match *self {
+            Error::Refused(ref msg) => msg.clone(),
+            Error::Unavailable(what) => format!(
+                "{} temporarily unavailable. Please try again later.\n",
+                what
+            ),
+            _ => format!("Something went wrong on our end. Please try again later.\n"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Refused(ref msg) => write!(f, "{}", msg.trim_end()),
+            Error::Unavailable(what) => write!(f, "{} unavailable", what),
+            Error::Store(ref e) | Error::Mail(ref e) | Error::Hash(ref e) => write!(f, "{}", e),
+            Error::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use account::Role;
+use config::CONFIG;
+use error::Result;
+use shared::lock;
+
+lazy_static! {
+    // Help topics, along with the modification time of the file they were read from
+    static ref HELP: Mutex<(Option<SystemTime>, HelpFile)> =
+        Mutex::new((None, HelpFile::default()));
+}
+
+/// A page of help that isn't about any one command, or adds to what a command says of itself
+#[derive(Debug, Clone)]
+pub struct Topic {
+    pub name: String,
+    pub keywords: Vec<String>, // Other words that find this topic in a search
+    pub see_also: Vec<String>, // Related topics and commands
+    pub role: Role,            // The least privileged role that may read the topic
+    pub body: String,
+}
+
+impl Topic {
+    fn new(name: &str) -> Topic {
+        Topic {
+            name: name.to_string(),
+            keywords: Vec::new(),
+            see_also: Vec::new(),
+            role: Role::Player,
+            body: String::new(),
+        }
+    }
+
+    // Whether the word appears in the topic's name, keywords or text
+    fn mentions(&self, word: &str) -> bool {
+        self.name.contains(word)
+            || self.keywords.iter().any(|k| k.contains(word))
+            || self.body.to_lowercase().contains(word)
+    }
+}
+
+// The help file: the comments at its head, and its topics in order
+#[derive(Debug, Default)]
+struct HelpFile {
+    header: String,
+    topics: Vec<Topic>,
+}
+
+impl HelpFile {
+    // Each topic starts with "== name", followed by optional "keywords:", "see also:" and
+    // "role:" lines, a blank line, and its text
+    fn parse(text: &str) -> HelpFile {
+        let mut file = HelpFile::default();
+        let mut in_body = false;
+        for line in text.lines() {
+            if line.starts_with("== ") {
+                // Topics are looked up in lower case, however the file spells them
+                file.topics.push(Topic::new(&line[3..].trim().to_lowercase()));
+                in_body = false;
+                continue;
+            }
+            let topic = match file.topics.last_mut() {
+                Some(topic) => topic,
+                None => {
+                    file.header.push_str(line);
+                    file.header.push('\n');
+                    continue;
+                }
+            };
+            if in_body {
+                topic.body.push_str(line);
+                topic.body.push('\n');
+            } else if line.trim().is_empty() {
+                in_body = true;
+            } else if line.starts_with("keywords:") {
+                topic.keywords = line[9..].split_whitespace().map(|w| w.to_lowercase()).collect();
+            } else if line.starts_with("see also:") {
+                topic.see_also = line[9..]
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect();
+            } else if line.starts_with("role:") {
+                match Role::parse(line[5..].trim()) {
+                    Some(role) => topic.role = role,
+                    None => warn!("Unknown role in help topic {}: {}", topic.name, line),
+                }
+            } else {
+                // A topic with no headers may start its text straight away
+                in_body = true;
+                topic.body.push_str(line);
+                topic.body.push('\n');
+            }
+        }
+        for topic in &mut file.topics {
+            let trimmed = topic.body.trim().to_string();
+            topic.body = if trimmed.is_empty() {
+                trimmed
+            } else {
+                trimmed + "\n"
+            };
+        }
+        file
+    }
+
+    fn render(&self) -> String {
+        let mut text = self.header.clone();
+        for topic in &self.topics {
+            text.push_str(&format!("== {}\n", topic.name));
+            if !topic.keywords.is_empty() {
+                text.push_str(&format!("keywords: {}\n", topic.keywords.join(" ")));
+            }
+            if !topic.see_also.is_empty() {
+                text.push_str(&format!("see also: {}\n", topic.see_also.join(", ")));
+            }
+            if topic.role != Role::Player {
+                text.push_str(&format!("role: {}\n", topic.role.name()));
+            }
+            text.push_str(&format!("\n{}\n", topic.body.trim_end()));
+        }
+        text
+    }
+}
+
+// Read the help file, rereading it whenever it has changed, and work with its topics
+fn with_help<T, F: FnOnce(&mut HelpFile) -> T>(f: F) -> T {
+    let path = &CONFIG.game.help_file;
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut cache = lock(&HELP);
+
+    if modified.is_some() && modified != cache.0 {
+        let mut text = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut text)) {
+            Ok(_) => {
+                *cache = (modified, HelpFile::parse(&text));
+                info!("Loaded {} help topics from {}", cache.1.topics.len(), path);
+            }
+            Err(e) => warn!("Could not read help topics from {}. {:?}", path, e),
+        }
+    }
+    f(&mut cache.1)
+}
+
+/// The topic with the given name, if the role may read it
+pub fn topic(name: &str, role: Role) -> Option<Topic> {
+    let name = name.to_lowercase();
+    with_help(|h| {
+        h.topics
+            .iter()
+            .find(|t| t.name == name && t.role <= role)
+            .cloned()
+    })
+}
+
+/// The names of every topic the role may read
+pub fn names(role: Role) -> Vec<String> {
+    with_help(|h| {
+        h.topics
+            .iter()
+            .filter(|t| t.role <= role)
+            .map(|t| t.name.clone())
+            .collect()
+    })
+}
+
+/// Topics the role may read whose names begin with the word, or failing that, which mention it
+pub fn search(word: &str, role: Role) -> Vec<Topic> {
+    let word = word.to_lowercase();
+    with_help(|h| {
+        let visible = h.topics.iter().filter(|t| t.role <= role);
+        let named: Vec<Topic> = visible
+            .clone()
+            .filter(|t| t.name.starts_with(&word))
+            .cloned()
+            .collect();
+        if !named.is_empty() {
+            return named;
+        }
+        visible.filter(|t| t.mentions(&word)).cloned().collect()
+    })
+}
+
+/// Change a topic, creating it if there is no topic by that name, and save the help file
+pub fn edit<F: FnOnce(&mut Topic)>(name: &str, change: F) -> Result<()> {
+    let name = name.to_lowercase();
+    with_help(|h| {
+        if !h.topics.iter().any(|t| t.name == name) {
+            h.topics.push(Topic::new(&name));
+        }
+        if let Some(topic) = h.topics.iter_mut().find(|t| t.name == name) {
+            change(topic);
+        }
+        save(h)
+    })
+}
+
+/// Remove a topic and save the help file. Returns whether there was such a topic.
+pub fn delete(name: &str) -> Result<bool> {
+    let name = name.to_lowercase();
+    with_help(|h| {
+        let before = h.topics.len();
+        h.topics.retain(|t| t.name != name);
+        if h.topics.len() == before {
+            return Ok(false);
+        }
+        save(h).map(|_| true)
+    })
+}
+
+// Write the topics back to the help file, so edits survive a restart and can be committed
+fn save(help: &HelpFile) -> Result<()> {
+    let path = &CONFIG.game.help_file;
+    File::create(path).and_then(|mut f| f.write_all(help.render().as_bytes()))?;
+    info!("Saved {} help topics to {}", help.topics.len(), path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lowercases_topic_names() {
+        let file = HelpFile::parse("== Combat\n\nHit things.\n");
+        assert_eq!(file.topics[0].name, "combat");
+    }
+
+    #[test]
+    fn render_reads_back_the_same_topics() {
+        let mut topic = Topic::new("combat");
+        topic.keywords = vec!["fight".to_string()];
+        topic.role = Role::Builder;
+        topic.body = "keywords: not a header\nrole: nor this\n".to_string();
+        let file = HelpFile {
+            header: "# Help topics\n".to_string(),
+            topics: vec![topic, Topic::new("empty")],
+        };
+        let read = HelpFile::parse(&file.render());
+        assert_eq!(read.header, file.header);
+        assert_eq!(read.topics.len(), 2);
+        assert_eq!(read.topics[0].keywords, file.topics[0].keywords);
+        assert_eq!(read.topics[0].role, Role::Builder);
+        assert_eq!(read.topics[0].body, file.topics[0].body);
+        assert_eq!(read.topics[1].name, "empty");
+    }
+}
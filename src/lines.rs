@@ -5,6 +5,7 @@ use tokio::net::TcpStream;
 use tokio::prelude::*;
 
 use shared::*;
+use telnet::{self, Options, IAC, GA};
 
 use super::Rx;
 
@@ -17,6 +18,8 @@ use super::Rx;
 #[derive(Debug)]
 pub struct RecvLines {
     pub insock: ReadHalf<TcpStream>, // The read half of the TCP socket
+    pub telnet: Options,             // Telnet options the client has agreed to
+    raw: BytesMut,                   // What the client sent, telnet commands and all
     rd: BytesMut,                    // Internal read buffer
 }
 
@@ -24,6 +27,8 @@ impl RecvLines {
     pub fn new(insock: ReadHalf<TcpStream>) -> Self {
         RecvLines {
             insock,
+            telnet: Options::default(),
+            raw: BytesMut::new(),
             rd: BytesMut::new(),
         }
     }
@@ -32,14 +37,15 @@ impl RecvLines {
     fn fill_read_buf(&mut self) -> Poll<(), io::Error> {
         loop {
             // Reserve capacity for the buffer. This might result in an internal allocation.
-            self.rd.reserve(1024);
+            self.raw.reserve(1024);
 
             // Read data into the buffer.
-            let n = try_ready!(self.insock.read_buf(&mut self.rd));
+            let n = try_ready!(self.insock.read_buf(&mut self.raw));
 
             if n == 0 {
                 return Ok(Async::Ready(()));
             }
+            self.telnet.filter(&mut self.raw, &mut self.rd);
         }
     }
 }
@@ -85,7 +91,13 @@ impl SendLines {
         // I acknowledge that doing this will block the thread until the socket is Ready, which is
         // not in the spirit of Asynchronous I/O. But I could not find any other way of making sure
         // the player was given this prompt consistently.
-        while let Result::Ok(Async::NotReady) = outsock.poll_write(&Bytes::from(SPLASH)) {}
+        // Nothing is known of the client yet, so the prompt ends with GA
+        let mut greeting = telnet::OFFER.to_vec();
+        greeting.extend_from_slice(SPLASH.as_bytes());
+        greeting.extend_from_slice(MENU_PROMPT.as_bytes());
+        greeting.extend_from_slice(&[IAC, GA]);
+        let greeting = Bytes::from(greeting);
+        while let Result::Ok(Async::NotReady) = outsock.poll_write(&greeting) {}
         while let Result::Ok(Async::NotReady) = outsock.poll_flush() {}
 
         SendLines { outsock, rx }
@@ -0,0 +1,251 @@
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process;
+use std::sync::mpsc::{self, SendError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use config::CONFIG;
+use error::{Error, Result};
+use shared::lock;
+
+lazy_static! {
+    /// Where all outgoing mail is posted, to be sent through the transport chosen in the
+    /// configuration file
+    pub static ref MAILER: Mailer = {
+        let conf = &CONFIG.mail;
+        Mailer::new(match conf.transport.as_str() {
+            "smtp" => Box::new(SmtpTransport::new(&conf.smtp_server, conf.smtp_port, &conf.from)),
+            "memory" => Box::new(MemoryTransport::new()),
+            _ => Box::new(FileTransport::new(&conf.file)),
+        })
+    };
+}
+
+/// A message to be sent to a player
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Something that can deliver mail to players
+pub trait Transport: Send + Sync {
+    fn send(&self, email: &Email) -> Result<()>;
+}
+
+// A message waiting to be sent, and what to do with the outcome once it has been
+type Job = (Email, Box<dyn FnOnce(Result<()>) + Send>);
+
+/// Sends mail from a thread of its own, so that a slow mail server never holds up the game
+pub struct Mailer {
+    queue: Mutex<Sender<Job>>,
+}
+
+impl Mailer {
+    /// Start a thread sending mail through the transport
+    pub fn new(transport: Box<dyn Transport>) -> Self {
+        let (queue, jobs) = mpsc::channel::<Job>();
+        let started = thread::Builder::new()
+            .name("mailer".to_string())
+            .spawn(move || {
+                for (email, done) in jobs {
+                    done(transport.send(&email));
+                }
+            });
+        if let Err(e) = started {
+            error!("Could not start the mail thread: {:?}", e);
+            process::exit(1);
+        }
+        Mailer {
+            queue: Mutex::new(queue),
+        }
+    }
+
+    /// Queue a message to be sent, calling `done` from the mail thread with the outcome
+    pub fn post<F: FnOnce(Result<()>) + Send + 'static>(&self, email: Email, done: F) {
+        let job: Job = (email, Box::new(done));
+        if let Err(SendError((_, done))) = lock(&self.queue).send(job) {
+            done(Err(Error::Mail(format!("The mail thread has stopped"))));
+        }
+    }
+}
+
+// TODO Support STARTTLS and authentication so mail can be relayed through external providers.
+/// Delivers mail to an SMTP relay, such as the MTA running on the server's own host
+pub struct SmtpTransport {
+    server: String,
+    port: u16,
+    from: String,
+}
+
+impl SmtpTransport {
+    pub fn new(server: &str, port: u16, from: &str) -> Self {
+        SmtpTransport {
+            server: server.to_string(),
+            port,
+            from: from.to_string(),
+        }
+    }
+
+    // Read a (possibly multi-line) reply from the server and check its status code
+    fn reply(reader: &mut dyn BufRead, expect: &str) -> Result<()> {
+        loop {
+            let mut line = String::new();
+            reader
+                .read_line(&mut line)
+                .map_err(|e| Error::Mail(format!("SMTP read error: {:?}", e)))?;
+            if !line.starts_with(expect) {
+                return Err(Error::Mail(format!("Unexpected SMTP reply: {}", line.trim())));
+            }
+            // Continuation lines have a '-' after the status code, the last line has a space
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+
+    // Send a single command and check the server's reply
+    fn command(
+        writer: &mut dyn Write,
+        reader: &mut dyn BufRead,
+        cmd: &str,
+        expect: &str,
+    ) -> Result<()> {
+        write!(writer, "{}\r\n", cmd)
+            .map_err(|e| Error::Mail(format!("SMTP write error: {:?}", e)))?;
+        SmtpTransport::reply(reader, expect)
+    }
+}
+
+impl Transport for SmtpTransport {
+    fn send(&self, email: &Email) -> Result<()> {
+        let stream = TcpStream::connect((self.server.as_str(), self.port))
+            .map_err(|e| {
+                Error::Mail(format!("Could not connect to {}: {:?}", self.server, e))
+            })?;
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(10)));
+        let mut reader = BufReader::new(stream
+            .try_clone()
+            .map_err(|e| Error::Mail(format!("SMTP socket error: {:?}", e)))?);
+        let mut writer = stream;
+
+        SmtpTransport::reply(&mut reader, "220")?;
+        SmtpTransport::command(&mut writer, &mut reader, "HELO redmud", "250")?;
+        SmtpTransport::command(
+            &mut writer,
+            &mut reader,
+            &format!("MAIL FROM:<{}>", self.from),
+            "250",
+        )?;
+        SmtpTransport::command(
+            &mut writer,
+            &mut reader,
+            &format!("RCPT TO:<{}>", email.to),
+            "25",
+        )?;
+        SmtpTransport::command(&mut writer, &mut reader, "DATA", "354")?;
+
+        // Lines beginning with '.' must be escaped so they don't end the message early
+        let body: String = email
+            .body
+            .lines()
+            .map(|l| {
+                if l.starts_with('.') {
+                    format!(".{}\r\n", l)
+                } else {
+                    format!("{}\r\n", l)
+                }
+            })
+            .collect();
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}.",
+            self.from, email.to, email.subject, body
+        );
+        SmtpTransport::command(&mut writer, &mut reader, &message, "250")?;
+        SmtpTransport::command(&mut writer, &mut reader, "QUIT", "221")
+    }
+}
+
+/// Appends mail to a file instead of sending it, for servers without access to a mail relay
+pub struct FileTransport {
+    path: String,
+}
+
+impl FileTransport {
+    pub fn new(path: &str) -> Self {
+        FileTransport {
+            path: path.to_string(),
+        }
+    }
+}
+
+impl Transport for FileTransport {
+    fn send(&self, email: &Email) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .map_err(|e| Error::Mail(format!("Could not open {}: {:?}", self.path, e)))?;
+        write!(
+            file,
+            "To: {}\nSubject: {}\n\n{}\n\n",
+            email.to, email.subject, email.body
+        ).map_err(|e| Error::Mail(format!("Could not write to {}: {:?}", self.path, e)))
+    }
+}
+
+/// Keeps mail in memory so tests can inspect what would have been sent. Clones share the same
+/// mail, so a test can keep one while a `Mailer` sends through another.
+#[derive(Clone)]
+pub struct MemoryTransport {
+    sent: Arc<Mutex<Vec<Email>>>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        MemoryTransport {
+            sent: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Every message sent so far, oldest first
+    #[cfg(test)]
+    pub fn sent(&self) -> Vec<Email> {
+        lock(&self.sent).clone()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&self, email: &Email) -> Result<()> {
+        lock(&self.sent).push(email.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mailer_sends_through_its_transport() {
+        let memory = MemoryTransport::new();
+        let mailer = Mailer::new(Box::new(memory.clone()));
+        let (done, outcome) = mpsc::channel();
+        let email = Email {
+            to: "player@example.com".to_string(),
+            subject: "Hello".to_string(),
+            body: "Welcome to RedMUD.\n".to_string(),
+        };
+        mailer.post(email, move |sent| done.send(sent.is_ok()).unwrap());
+        assert!(outcome.recv().unwrap());
+        let sent = memory.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "player@example.com");
+        assert_eq!(sent[0].subject, "Hello");
+    }
+}
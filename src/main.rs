@@ -5,12 +5,17 @@
 // Disclosures:
 //  Net code was templated from chat examples in Tokio; https://github.com/tokio-rs/tokio
 
+extern crate argon2;
 extern crate argon2rs;
+extern crate base32;
 extern crate bytes;
 #[macro_use]
 extern crate diesel;
 #[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
 extern crate futures;
+extern crate hmac;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -18,28 +23,59 @@ extern crate log;
 extern crate r2d2;
 extern crate r2d2_diesel;
 extern crate rand;
+#[cfg(feature = "sqlite")]
+#[macro_use]
+extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate sha1;
 extern crate simplelog;
 extern crate tk_listen;
 extern crate tokio;
+extern crate toml;
 
 use bytes::Bytes;
 use futures::sync::mpsc;
 use tk_listen::*;
 use tokio::net::TcpListener;
 use tokio::prelude::*;
+use tokio::timer::Interval;
 
+use std::env;
 use std::fs::OpenOptions;
+use std::process;
 //use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 mod account;
+mod alias;
+mod ban;
+mod channel;
+mod character;
+mod chargen;
 mod cmd;
+mod config;
+mod error;
+mod help;
 mod lines;
+mod mail;
+mod names;
+mod pager;
+mod password;
 mod player;
 mod schema;
 mod shared;
+mod store;
+mod telnet;
+mod totp;
+mod world;
 
+use account::Account;
+use ban::Ban;
 use player::Player;
+use store::STORE;
+use world::WORLD;
 
 // TODO Make these bounded
 // Shorthand for the transmit/receive parts of the message channel.
@@ -50,6 +86,19 @@ pub fn main() {
     init_logger();
     // TODO read configs from file
 
+    // Bring the database up to date before anyone can connect
+    if let Err(e) = STORE.migrate() {
+        error!("Could not migrate the database. {}", e);
+        process::exit(1);
+    }
+    if env::args().any(|arg| arg == "--migrate-only") {
+        info!("Database is up to date.");
+        return;
+    }
+    // Load the world now, rather than when the first player enters it
+    lazy_static::initialize(&WORLD);
+    ban::load_address_bans();
+
     let addr = "127.0.0.1:3389".parse().unwrap();
     let listener = TcpListener::bind(&addr).unwrap();
 
@@ -64,12 +113,35 @@ pub fn main() {
         .incoming()
         .sleep_on_error(Duration::from_secs(1))
         .map(move |socket| {
+            // Turn away banned addresses before they get as far as the login menu, and every
+            // address if the bans can't be checked
+            let peer = socket.peer_addr().ok();
+            let refusal = match peer.map(|a| Ban::find_address(a.ip())) {
+                Some(Ok(Some(ban))) => {
+                    info!("Refused connection from banned address: {:?}", peer);
+                    Some(ban.notice())
+                }
+                Some(Err(e)) => {
+                    warn!("Refused connection from {:?}, since bans can't be checked", peer);
+                    Some(e.notice())
+                }
+                _ => None,
+            };
+            if let Some(notice) = refusal {
+                tokio::spawn(tokio::io::write_all(socket, notice).then(|_| Ok(())));
+                return Ok(());
+            }
+
             // Spawn a task to process the connection
-            let player = Player::new(socket);
-            let connection = player.map_err(|e| {
-                error!("Connection error = {:?}", e);
-            });
-            tokio::spawn(connection);
+            match Player::new(socket) {
+                Ok(player) => {
+                    let connection = player.map_err(|e| {
+                        error!("Connection error = {:?}", e);
+                    });
+                    tokio::spawn(connection);
+                }
+                Err(e) => warn!("Dropped a connection before it was set up. {}", e),
+            }
             Ok(())
         })
         .listen(1000)
@@ -77,9 +149,44 @@ pub fn main() {
             error!("Fatal socket error. {:?}", err);
         });
 
+    // Periodically purge accounts whose deletion grace period has passed
+    let purger = Interval::new(Instant::now(), Duration::from_secs(60 * 60))
+        .for_each(|_| {
+            Account::purge_deleted();
+            Ok(())
+        })
+        .map_err(|err| {
+            error!("Account purge timer error. {:?}", err);
+        });
+
+    // Load the address bans once the database is back, if it was down when they were needed
+    let ban_loader = Interval::new(Instant::now(), Duration::from_secs(60))
+        .for_each(|_| {
+            ban::retry_address_bans();
+            Ok(())
+        })
+        .map_err(|err| {
+            error!("Ban loading timer error. {:?}", err);
+        });
+
+    // Periodically remove characters that have been link-dead for too long
+    let reaper = Interval::new(Instant::now(), Duration::from_secs(30))
+        .for_each(|_| {
+            player::reap_link_dead();
+            Ok(())
+        })
+        .map_err(|err| {
+            error!("Link-dead timer error. {:?}", err);
+        });
+
     info!("Server running on {}", addr);
 
-    tokio::run(server);
+    tokio::run(future::lazy(|| {
+        tokio::spawn(purger);
+        tokio::spawn(reaper);
+        tokio::spawn(ban_loader);
+        server
+    }));
 }
 
 // Set up logging utility for server monitoring and debugging info
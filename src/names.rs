@@ -0,0 +1,118 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use cmd::{command_names, is_login_command};
+use config::CONFIG;
+use error::{Error, Result};
+use shared::lock;
+
+lazy_static! {
+    // Forbidden words, along with the modification time of the file they were read from
+    static ref FORBIDDEN: Mutex<(Option<SystemTime>, Vec<String>)> = Mutex::new((None, Vec::new()));
+}
+
+/// Check a proposed account or character name against the server's naming rules. This does not
+/// check whether the name is already taken, since accounts and characters live in separate tables.
+pub fn validate(name: &str) -> Result<()> {
+    let rules = &CONFIG.names;
+    let len = name.chars().count();
+    let lowered = name.to_lowercase();
+
+    if len < rules.min_length || len > rules.max_length {
+        return Err(Error::Refused(format!(
+            "Names must be between {} and {} characters long.\n",
+            rules.min_length, rules.max_length
+        )));
+    }
+    if !name.chars().next().map_or(false, |c| c.is_ascii_alphabetic()) {
+        return Err(Error::Refused(format!("Names must begin with a letter.\n")));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|&c| !c.is_ascii_alphabetic() && !rules.extra_chars.contains(c))
+    {
+        return Err(Error::Refused(if rules.extra_chars.is_empty() {
+            format!("Names may only contain letters, and '{}' is not a letter.\n", c)
+        } else {
+            format!(
+                "Names may only contain letters and any of \"{}\", and '{}' is not one of them.\n",
+                rules.extra_chars, c
+            )
+        }));
+    }
+
+    if command_names().contains(&&*lowered) || is_login_command(&lowered) {
+        return Err(Error::Refused(format!(
+            "'{}' is reserved because it would be mistaken for a command.\n",
+            name
+        )));
+    }
+    if rules.reserved.iter().any(|r| r.to_lowercase() == lowered) {
+        return Err(Error::Refused(format!(
+            "'{}' is reserved for a character in the world. Please choose a different name.\n",
+            name
+        )));
+    }
+    if forbidden_words().iter().any(|w| lowered.contains(&**w)) {
+        return Err(Error::Refused(format!(
+            "'{}' contains a word that is not allowed in names. Please choose a different name.\n",
+            name
+        )));
+    }
+    Ok(())
+}
+
+// Read the forbidden words list, reloading it whenever an admin has changed the file
+fn forbidden_words() -> Vec<String> {
+    let path = &CONFIG.names.forbidden_file;
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut cache = lock(&FORBIDDEN);
+
+    if modified.is_some() && modified != cache.0 {
+        match File::open(path) {
+            Ok(file) => {
+                let words = BufReader::new(file)
+                    .lines()
+                    .filter_map(|l| l.ok())
+                    .map(|l| l.trim().to_lowercase())
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .collect();
+                *cache = (modified, words);
+                info!("Loaded forbidden names from {}", path);
+            }
+            Err(e) => warn!("Could not read forbidden names from {}. {:?}", path, e),
+        }
+    }
+    cache.1.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_accepts_names_within_the_rules() {
+        assert!(validate("Alice").is_ok());
+        assert!(validate("O'Brien").is_ok());
+        assert!(validate("Anne-Marie").is_ok());
+    }
+
+    #[test]
+    fn validate_refuses_names_that_break_the_rules() {
+        assert!(validate("Al").is_err());
+        assert!(validate("Abcdefghijklmnopq").is_err());
+        assert!(validate("-Alice").is_err());
+        assert!(validate("Alice2").is_err());
+        assert!(validate("Guard").is_err());
+    }
+
+    #[test]
+    fn validate_refuses_commands_and_their_abbreviations() {
+        assert!(validate("look").is_err());
+        assert!(validate("Register").is_err());
+        assert!(validate("reg").is_err());
+        assert!(validate("logi").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+/// Output too long for the player's screen, shown a page at a time
+#[derive(Debug)]
+pub struct Pager {
+    lines: Vec<String>,
+    top: usize,  // First line of the page on screen
+    page: usize, // Lines per page, leaving room for the pager's prompt
+}
+
+impl Pager {
+    /// Split the text into pages for a screen of the given height, or 0 to leave it whole.
+    /// Returns the first page, along with a pager for the rest if there is more.
+    pub fn start(text: String, height: usize) -> (String, Option<Pager>) {
+        let page = height.saturating_sub(1).max(1);
+        if height == 0 || text.split_inclusive('\n').count() <= page {
+            return (text, None);
+        }
+        let pager = Pager {
+            lines: text.split_inclusive('\n').map(|l| l.to_string()).collect(),
+            top: 0,
+            page,
+        };
+        (pager.current(), Some(pager))
+    }
+
+    // The page on screen
+    fn current(&self) -> String {
+        let end = (self.top + self.page).min(self.lines.len());
+        self.lines[self.top..end].concat()
+    }
+
+    /// Move on to the next page
+    pub fn forward(&mut self) -> String {
+        self.top = (self.top + self.page).min(self.lines.len());
+        self.current()
+    }
+
+    /// Go back to the page before
+    pub fn back(&mut self) -> String {
+        self.top = self.top.saturating_sub(self.page);
+        self.current()
+    }
+
+    /// Whether the last page is on screen
+    pub fn finished(&self) -> bool {
+        self.top + self.page >= self.lines.len()
+    }
+
+    /// Tells the player how far through they are and what they can do
+    pub fn prompt(&self) -> String {
+        let shown = (self.top + self.page).min(self.lines.len());
+        format!(
+            "-- More ({}%) -- Enter to continue, 'b' to go back, 'q' to stop: ",
+            shown * 100 / self.lines.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(lines: usize) -> String {
+        (1..lines + 1).map(|n| format!("{}\n", n)).collect()
+    }
+
+    #[test]
+    fn start_leaves_short_text_whole() {
+        assert!(Pager::start(numbered(3), 4).1.is_none());
+        assert!(Pager::start(numbered(30), 0).1.is_none());
+    }
+
+    #[test]
+    fn pager_moves_forward_and_back_a_page_at_a_time() {
+        // A screen of four lines leaves three for text and one for the prompt
+        let (first, pager) = Pager::start(numbered(7), 4);
+        let mut pager = pager.unwrap();
+        assert_eq!(first, "1\n2\n3\n");
+        assert!(!pager.finished());
+        assert!(pager.prompt().contains("42%"));
+        assert_eq!(pager.forward(), "4\n5\n6\n");
+        assert_eq!(pager.forward(), "7\n");
+        assert!(pager.finished());
+        assert_eq!(pager.back(), "4\n5\n6\n");
+        assert_eq!(pager.back(), "1\n2\n3\n");
+        assert_eq!(pager.back(), "1\n2\n3\n");
+    }
+}
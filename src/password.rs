@@ -0,0 +1,110 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use argon2rs;
+use rand::{thread_rng, Rng};
+
+use std::convert::TryFrom;
+
+use config::CONFIG;
+use error::{Error, Result};
+
+// Build a hasher using the cost parameters from the configuration file
+fn hasher() -> Result<Argon2<'static>> {
+    let conf = &CONFIG.security;
+    let params = Params::new(
+        conf.argon_memory_kib,
+        conf.argon_iterations,
+        conf.argon_parallelism,
+        None,
+    ).map_err(|e| Error::Hash(format!("Invalid Argon2 parameters: {}", e)))?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Hash a password with Argon2id, producing a PHC string that records the algorithm, cost
+/// parameters and salt along with the hash itself
+pub fn hash(passwd: &str) -> Result<String> {
+    let mut salt = [0u8; 16];
+    thread_rng().fill(&mut salt[..]);
+    let salt =
+        SaltString::encode_b64(&salt).map_err(|e| Error::Hash(format!("Salt error: {}", e)))?;
+
+    hasher()?
+        .hash_password(passwd.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| Error::Hash(format!("Hashing error: {}", e)))
+}
+
+/// Check a password against a PHC string. The comparison is done in constant time.
+pub fn verify(passwd: &str, phc: &str) -> bool {
+    match PasswordHash::new(phc) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(passwd.as_bytes(), &parsed)
+            .is_ok(),
+        Err(e) => {
+            error!("Unreadable password hash: {}", e);
+            false
+        }
+    }
+}
+
+/// Whether a PHC string was made with a different algorithm or cost than is now configured
+pub fn needs_rehash(phc: &str) -> bool {
+    let parsed = match PasswordHash::new(phc) {
+        Ok(parsed) => parsed,
+        Err(_) => return true,
+    };
+    let conf = &CONFIG.security;
+    parsed.algorithm != Algorithm::Argon2id.ident()
+        || parsed.version != Some(Version::V0x13 as u32)
+        || match Params::try_from(&parsed) {
+            Ok(params) => {
+                params.m_cost() != conf.argon_memory_kib
+                    || params.t_cost() != conf.argon_iterations
+                    || params.p_cost() != conf.argon_parallelism
+            }
+            Err(_) => true,
+        }
+}
+
+// TODO Remove once every account has logged in since passwords moved to PHC strings.
+/// Check a password against the raw salt and Argon2i hash that accounts used to store
+pub fn verify_legacy(passwd: &str, salt: &[u8], hash: &[u8]) -> bool {
+    let mut given_hash = vec![0u8; 32];
+    let argon = argon2rs::Argon2::default(argon2rs::Variant::Argon2i);
+    argon.hash(&mut given_hash, passwd.as_bytes(), salt, &[], &[]);
+    constant_time_eq(&given_hash, hash)
+}
+
+/// Compare two byte strings without returning early, so timing reveals nothing about where they
+/// differ
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_accepts_only_the_password_hashed() {
+        let phc = hash("correct horse").unwrap();
+        assert!(verify("correct horse", &phc));
+        assert!(!verify("Correct horse", &phc));
+        assert!(!verify("correct horse", "not a hash"));
+    }
+
+    #[test]
+    fn needs_rehash_spots_hashes_made_at_another_cost() {
+        assert!(!needs_rehash(&hash("correct horse").unwrap()));
+        let cheap = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(8, 1, 1, None).unwrap(),
+        );
+        let salt = SaltString::encode_b64(b"0123456789abcdef").unwrap();
+        let phc = cheap.hash_password(b"correct horse", &salt).unwrap().to_string();
+        assert!(verify("correct horse", &phc));
+        assert!(needs_rehash(&phc));
+        assert!(needs_rehash("not a hash"));
+    }
+}
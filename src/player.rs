@@ -1,23 +1,50 @@
 use bytes::Bytes;
-use futures::sync::mpsc;
+use futures::sync::{mpsc, oneshot};
 use tokio::io;
 use tokio::net::TcpStream;
 use tokio::prelude::*;
 
+use std::collections::VecDeque;
+use std::mem;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
 use account::Account;
+use channel;
+use character::Character;
+use chargen::Draft;
 use cmd::*;
+use config::CONFIG;
+use error::Result;
 use lines::{RecvLines, SendLines};
+use pager::Pager;
 use shared::*;
+use world;
 
 use super::Tx;
 
+// Commands kept in each session's history
+const HISTORY_LENGTH: usize = 50;
+
+// Shorthand for the sending half of a session's control channel
+pub type Ctl = mpsc::UnboundedSender<Control>;
+
+/// Requests one session can make of another
+#[derive(Debug)]
+pub enum Control {
+    // The account has logged in elsewhere. The session should hand over its character, if it
+    // has one in the world, and close.
+    Takeover(oneshot::Sender<Option<Character>>),
+}
+
 #[derive(Debug)]
 enum State {
     Connected,     // Player just connected and has not yet logged in
+    Authenticating(Account), // Player gave the right password and owes a second factor
+    Resuming(Account), // Player logged in and is waiting for their old session to hand over
     Idle(Account), // Player is logged in but not in the game world
-    Playing(Account), // Player is playing
+    Creating(Account, Draft), // Player is creating a new character from the lobby
+    Playing(Account, Character), // Player is playing as one of their characters
                    //Prison,    // Player is being punished
 }
 
@@ -28,16 +55,23 @@ pub struct Player {
     addr: SocketAddr,   // The addr is saved so that the Drop impl can clean up its entry
     state: State,       // Player's activity state. Are they logged in?
     tx: Tx,
+    ctl: Ctl,           // Handed out so other sessions can reach this one once logged in
+    control: mpsc::UnboundedReceiver<Control>,
+    resume: Option<oneshot::Receiver<Option<Character>>>, // Pending handover from an old session
+    history: VecDeque<String>, // Commands the player entered recently, oldest first
+    queue: VecDeque<String>,   // Input waiting until the player can act on it
+    pager: Option<Pager>,      // The rest of a long message, while the player pages through it
 }
-//account: Account,          // A player account may have multiple characters
 
 impl Player {
-    pub fn new(sock: TcpStream) -> Player {
+    /// Set up a session for a new connection. This fails if the peer has already gone.
+    pub fn new(sock: TcpStream) -> Result<Player> {
         // Get the client socket address
-        let addr = sock.peer_addr().unwrap();
+        let addr = sock.peer_addr()?;
 
         // Create a channel for this peer
         let (tx, rx) = mpsc::unbounded();
+        let (ctl, control) = mpsc::unbounded();
 
         // Split the socket so we can process input and output separately
         let (recv, send) = sock.split();
@@ -45,52 +79,409 @@ impl Player {
         let outsock = SendLines::new(send, rx);
 
         // Add this player to the list.
-        SHARE.conn_players.lock().unwrap().insert(addr, tx.clone());
+        lock(&SHARE.conn_players).insert(addr, tx.clone());
 
-        Player {
+        Ok(Player {
             insock,
             outsock,
             addr,
             state: State::Connected,
             tx,
-        }
+            ctl,
+            control,
+            resume: None,
+            history: VecDeque::new(),
+            queue: VecDeque::new(),
+            pager: None,
+        })
     }
 
     // TODO Does it make sense to separate command parsing from command processing? Why?
     // Parse player's input and process any valid commands for their current connection state
-    fn process_input(&mut self, input: &[u8]) -> Option<String> {
-        let mut line = String::from_utf8_lossy(input).into_owned();
-        line.retain(|c| !c.is_control());
+    fn process_input(&mut self, line: String) -> Option<String> {
+        let recalled = line.trim_start().starts_with('!');
+        let line = match self.remember(line) {
+            Ok(line) => line,
+            Err(msg) => return Some(msg),
+        };
+        // Show the player what they recalled from their history before running it
+        let echo = if recalled {
+            format!("{}\n", line.trim())
+        } else {
+            String::new()
+        };
+
         // Process player input based on their current state
         let action = match self.state {
-            State::Connected => match cmd_connected(line) {
-                Disconnect => None,
-                Login(acct, s) => {
-                    // Put the player into the Playing state and spawn them into the world.
-                    SHARE.conn_players.lock().unwrap().remove(&self.addr);
-                    SHARE
-                        .play_players
-                        .lock()
-                        .unwrap()
-                        .insert(acct.name.clone(), self.tx.clone());
-                    self.state = State::Playing(acct);
-                    Some(s)
-                }
-                Noop(s) => Some(s),
+            State::Connected => {
+                let action = cmd_connected(line);
+                self.apply(action)
+            }
+            State::Authenticating(_) => match mem::replace(&mut self.state, State::Connected) {
+                // Whatever the outcome, the player is done authenticating
+                State::Authenticating(acct) => match cmd_second_factor(acct, line) {
+                    Noop(s) => Some(format!("{}{}", s, SPLASH)),
+                    action => self.apply(action),
+                },
+                _ => unreachable!(),
             },
-            State::Idle(ref acct) => {
-                // If they logout, set Account to None and put them in Connected state
-                //Some(format!("You are Idle\n"))
-                unimplemented!();
+            State::Resuming(_) => Some(format!(
+                "Please wait while your existing session is taken over.\n"
+            )),
+            State::Idle(ref mut acct) => {
+                let action = cmd_idle(acct, line);
+                self.apply(action)
+            }
+            State::Creating(ref acct, ref mut draft) => {
+                let action = cmd_creating(acct, draft, line);
+                self.apply_creating(action)
+            }
+            State::Playing(ref mut acct, ref mut chr) => {
+                let (output, action) = cmd_playing(acct, chr, line);
+                self.apply(action).map(|s| output + &s)
+            }
+        };
+        action.map(|msg| format!("{}{}", echo, msg))
+    }
+
+    // Take a line the player entered and run it, unless they must wait, in which case it joins
+    // the queue of commands waiting their turn
+    fn receive(&mut self, input: &[u8]) -> Option<String> {
+        let mut line = String::from_utf8_lossy(input).into_owned();
+        line.retain(|c| !c.is_control());
+        if let Some(mut pager) = self.pager.take() {
+            match line.trim() {
+                "" => {
+                    let page = pager.forward();
+                    if !pager.finished() {
+                        self.pager = Some(pager);
+                    }
+                    return Some(page);
+                }
+                "b" => {
+                    let page = pager.back();
+                    self.pager = Some(pager);
+                    return Some(page);
+                }
+                "q" => return Some(String::new()),
+                // Anything else is a new command, and the rest of the paged output is dropped
+                _ => {}
+            }
+        }
+        if self.busy() {
+            // The player can always see and cancel what's waiting, without waiting themselves
+            match line.trim() {
+                "clear" => return self.apply(ClearQueue),
+                "queue" => return self.apply(ShowQueue),
+                _ => {}
+            }
+            let msg = format!(
+                "Queued: {} ({} waiting, enter 'clear' to cancel)\n",
+                line.trim(),
+                self.queue.len() + 1
+            );
+            self.queue.push_back(line);
+            return Some(msg);
+        }
+        self.queue.push_back(line);
+        self.run_queue()
+    }
+
+    // Whether the player must wait before their next command runs
+    fn busy(&self) -> bool {
+        match self.state {
+            State::Resuming(_) => true,
+            _ => false,
+        }
+    }
+
+    // Run queued commands in the order they were entered until none are left or the player
+    // must wait
+    fn run_queue(&mut self) -> Option<String> {
+        let mut output = String::new();
+        while !self.busy() {
+            match self.queue.pop_front() {
+                Some(line) => output.push_str(&self.process_input(line)?),
+                None => break,
             }
-            State::Playing(ref acct) => {
-                //State::Playing(ref acct) => match cmd_playing(line) {
-                // If they are enterring the world, put them into Playing state
-                // process input
-                unimplemented!();
+        }
+        Some(output)
+    }
+
+    // Swap '!' or '!<prefix>' for the most recent command it matches, and keep the command in the
+    // player's history unless it holds a password or the like. Only players in the lobby or the
+    // world have a history, since anything entered at the login menu may be a password.
+    fn remember(&mut self, line: String) -> ::std::result::Result<String, String> {
+        let (stage, role) = match self.state {
+            State::Idle(ref acct) => (Stage::Idle, acct.role()),
+            State::Playing(ref acct, _) => (Stage::Playing, acct.role()),
+            _ => return Ok(line),
+        };
+        let line = if line.trim().starts_with('!') {
+            let prefix = line.trim()[1..].trim().to_string();
+            match self.history.iter().rev().find(|h| h.starts_with(&prefix)) {
+                Some(found) => found.clone(),
+                None if prefix.is_empty() => {
+                    return Err(format!("There is nothing in your history to repeat.\n"))
+                }
+                None => {
+                    return Err(format!("Nothing in your history starts with '{}'.\n", prefix))
+                }
             }
+        } else {
+            line.trim().to_string()
         };
-        action
+        if !line.is_empty() && !is_secret(stage, role, &line) {
+            self.history.push_back(line.clone());
+            if self.history.len() > HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+        }
+        Ok(line)
+    }
+
+    // Finish logging in, either back into the world as the given character or into the lobby
+    fn resume_session(&mut self, chr: Option<Character>) -> String {
+        let acct = match mem::replace(&mut self.state, State::Connected) {
+            State::Resuming(acct) => acct,
+            state => {
+                self.state = state;
+                return String::new();
+            }
+        };
+        lock(&SHARE.play_players).insert(acct.name.clone(), self.tx.clone());
+        lock(&SHARE.controls).insert(acct.name.clone(), self.ctl.clone());
+
+        match chr {
+            Some(chr) => {
+                let msg = format!("You take control of {} again.\n{}", chr.name, world::look(&chr));
+                info!("{} reconnected to {}", acct.name, chr.name);
+                self.state = State::Playing(acct, chr);
+                msg
+            }
+            None => {
+                // Put the player into the Idle state so they can choose a character.
+                self.state = State::Idle(acct);
+                LOBBY.to_string()
+            }
+        }
+    }
+
+    // Check whether the old session has handed over yet
+    fn poll_resume(&mut self) {
+        if let Some(mut pending) = self.resume.take() {
+            let chr = match pending.poll() {
+                Ok(Async::NotReady) => {
+                    self.resume = Some(pending);
+                    return;
+                }
+                Ok(Async::Ready(chr)) => chr,
+                // The old session closed before it could answer. It may have left its character
+                // link-dead on the way out.
+                Err(_) => match self.state {
+                    State::Resuming(ref acct) => take_link_dead(&acct.name),
+                    _ => None,
+                },
+            };
+            let msg = self.resume_session(chr);
+            self.reply(Some(msg));
+            let _ = self.outsock.poll();
+        }
+    }
+
+    // Lines per page of output: the player's choice, else what their client reports, else the
+    // server's default. Zero leaves output unpaged.
+    fn page_length(&self) -> usize {
+        let chosen = match self.state {
+            State::Idle(ref acct) | State::Playing(ref acct, _) | State::Creating(ref acct, _) => {
+                acct.page_length()
+            }
+            _ => None,
+        };
+        chosen
+            .or(self.insock.telnet.height)
+            .unwrap_or(CONFIG.game.page_length)
+    }
+
+    // The prompt for whatever the player does next
+    fn prompt(&self) -> String {
+        if let Some(ref pager) = self.pager {
+            return pager.prompt();
+        }
+        match self.state {
+            State::Connected | State::Idle(_) => MENU_PROMPT.to_string(),
+            State::Playing(_, ref chr) => chr.prompt(),
+            // The second factor challenge and the creation wizard ask their own questions, and a
+            // resuming player can only wait
+            State::Authenticating(_) | State::Resuming(_) | State::Creating(..) => String::new(),
+        }
+    }
+
+    // Send the player what came of their input, then their prompt. Returns false once the
+    // session should close.
+    fn reply(&mut self, reply: Option<String>) -> bool {
+        match reply {
+            Some(msg) => {
+                let (msg, pager) = Pager::start(msg, self.page_length());
+                if pager.is_some() {
+                    self.pager = pager;
+                }
+                let mut out = msg.into_bytes();
+                out.extend_from_slice(self.prompt().as_bytes());
+                out.extend_from_slice(self.insock.telnet.end_of_prompt());
+                let _ = self.tx.unbounded_send(Bytes::from(out));
+                true
+            }
+            None => {
+                let _ = self.tx
+                    .unbounded_send(Bytes::from(&b"Thanks for playing!\n"[..]));
+                let _ = self.outsock.poll();
+                false
+            }
+        }
+    }
+
+    // Give up this session to a new login for the same account
+    fn hand_over(&mut self, reply: oneshot::Sender<Option<Character>>) {
+        // The new session now owns the account's entries in the shared maps, so this one must
+        // not remove them when it's dropped
+        let chr = match mem::replace(&mut self.state, State::Connected) {
+            State::Playing(acct, chr) => {
+                info!("{} took over the session playing {}", acct.name, chr.name);
+                Some(chr)
+            }
+            _ => None,
+        };
+        // If the new session has already gone, leave the character link-dead instead
+        if let Err(Some(chr)) = reply.send(chr) {
+            go_link_dead(chr);
+        }
+        let _ = self.tx.unbounded_send(Bytes::from(
+            &b"Your account has logged in from another connection. Goodbye!\n"[..],
+        ));
+        let _ = self.outsock.poll();
+    }
+
+    // Apply the result of a command to the player's state
+    fn apply(&mut self, action: Action) -> Option<String> {
+        match action {
+            Disconnect => {
+                // Leaving the world first keeps the character from being left link-dead
+                self.leave_world();
+                None
+            }
+            Challenge(acct, s) => {
+                // Hold on to the account until the player proves they own it
+                self.state = State::Authenticating(acct);
+                Some(s)
+            }
+            Login(acct, s) => {
+                lock(&SHARE.conn_players).remove(&self.addr);
+
+                // If the account is already logged in somewhere, ask that session to hand over
+                let old = lock(&SHARE.controls).remove(&acct.name);
+                if let Some(old) = old {
+                    let (reply, pending) = oneshot::channel();
+                    if old.unbounded_send(Control::Takeover(reply)).is_ok() {
+                        self.resume = Some(pending);
+                        self.state = State::Resuming(acct);
+                        return Some(format!("{}Taking over your existing session...\n", s));
+                    }
+                }
+
+                // Otherwise pick up a link-dead character, or go to the lobby
+                let chr = take_link_dead(&acct.name);
+                self.state = State::Resuming(acct);
+                Some(format!("{}{}", s, self.resume_session(chr)))
+            }
+            Logout(s) => {
+                // Take the player out of the lobby and return them to the login menu
+                if let State::Idle(ref acct) = self.state {
+                    lock(&SHARE.play_players).remove(&acct.name);
+                    lock(&SHARE.controls).remove(&acct.name);
+                }
+                lock(&SHARE.conn_players).insert(self.addr, self.tx.clone());
+                self.state = State::Connected;
+                Some(format!("{}{}", s, SPLASH))
+            }
+            Create(draft) => {
+                // Walk the player through character creation, starting from where they left off
+                let prompt = draft.prompt();
+                if let State::Idle(acct) = mem::replace(&mut self.state, State::Connected) {
+                    self.state = State::Creating(acct, draft);
+                }
+                Some(prompt)
+            }
+            Play(chr, s) => {
+                // Put the player into the Playing state and spawn them into the world.
+                if let State::Idle(acct) = mem::replace(&mut self.state, State::Connected) {
+                    world::enter(&chr);
+                    channel::tune_in(&acct);
+                    let view = world::look(&chr);
+                    self.state = State::Playing(acct, chr);
+                    return Some(format!("{}{}", s, view));
+                }
+                Some(s)
+            }
+            Leave(s) => {
+                self.leave_world();
+                Some(format!("{}{}", s, LOBBY))
+            }
+            Noop(s) => Some(s),
+            ShowHistory => {
+                let list: String = self.history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, line)| format!("  {:>3}  {}\n", i + 1, line))
+                    .collect();
+                Some(format!(
+                    "Your recent commands:\n{}Enter '!' to repeat the last, or '!<start>' the \
+                     last that begins that way.\n",
+                    list
+                ))
+            }
+            ShowQueue if self.queue.is_empty() => Some(format!("No commands are waiting.\n")),
+            ShowQueue => {
+                let list: String = self.queue
+                    .iter()
+                    .enumerate()
+                    .map(|(i, line)| format!("  {:>3}  {}\n", i + 1, line.trim()))
+                    .collect();
+                Some(format!("Commands waiting their turn:\n{}", list))
+            }
+            ClearQueue => {
+                let count = self.queue.len();
+                self.queue.clear();
+                match count {
+                    0 => Some(format!("No commands are waiting.\n")),
+                    1 => Some(format!("Cancelled the command that was waiting.\n")),
+                    n => Some(format!("Cancelled the {} commands that were waiting.\n", n)),
+                }
+            }
+        }
+    }
+
+    // Take the player's character out of the world and put the player back in the lobby
+    fn leave_world(&mut self) {
+        if let State::Playing(acct, chr) = mem::replace(&mut self.state, State::Connected) {
+            info!("{} has left the world", chr.name);
+            world::leave(&chr);
+            self.state = State::Idle(acct);
+        }
+    }
+
+    // Apply the result of a character creation step to the player's state
+    fn apply_creating(&mut self, action: CreateAction) -> Option<String> {
+        match action {
+            CreateAction::Continue(s) => Some(s),
+            CreateAction::Leave(s) => {
+                // Return the player to the lobby
+                if let State::Creating(acct, _) = mem::replace(&mut self.state, State::Connected) {
+                    self.state = State::Idle(acct);
+                }
+                Some(format!("{}{}", s, LOBBY))
+            }
+        }
     }
 }
 
@@ -103,26 +494,33 @@ impl Future for Player {
         // I need a better way of sending data to the receiver, this only updates them when they
         // send something to us.
         let _ = self.outsock.poll();
+        // Close this session if the account has logged in elsewhere
+        if let Ok(Async::Ready(Some(Control::Takeover(reply)))) = self.control.poll() {
+            self.hand_over(reply);
+            return Ok(Async::Ready(()));
+        }
         // Read new lines from the socket
         while let Async::Ready(line) = self.insock.poll()? {
             if let Some(message) = line {
-                match self.process_input(&message) {
-                    None => {
-                        let _ = self.tx
-                            .unbounded_send(Bytes::from(&b"Thanks for playing!\n"[..]));
-                        let _ = self.outsock.poll();
-                        return Ok(Async::Ready(()));
-                    }
-                    Some(msg) => {
-                        let _ = self.tx.unbounded_send(Bytes::from(msg));
-                    }
-                };
+                let reply = self.receive(&message);
+                if !self.reply(reply) {
+                    return Ok(Async::Ready(()));
+                }
             } else {
                 // EOF was reached, client has disconnected
                 return Ok(Async::Ready(()));
             }
             let _ = self.outsock.poll();
         }
+        self.poll_resume();
+        // Run whatever the player entered while they had to wait
+        if !self.queue.is_empty() && !self.busy() {
+            let reply = self.run_queue();
+            if !self.reply(reply) {
+                return Ok(Async::Ready(()));
+            }
+            let _ = self.outsock.poll();
+        }
 
         Ok(Async::NotReady)
     }
@@ -132,16 +530,54 @@ impl Future for Player {
 impl Drop for Player {
     fn drop(&mut self) {
         debug!("Player Disconnected");
-        match self.state {
-            State::Connected => {
-                SHARE.conn_players.lock().unwrap().remove(&self.addr);
+        match mem::replace(&mut self.state, State::Connected) {
+            State::Connected | State::Authenticating(_) | State::Resuming(_) => {
+                lock(&SHARE.conn_players).remove(&self.addr);
             }
-            State::Playing(ref acct) => {
-                SHARE.play_players.lock().unwrap().remove(&acct.name);
+            State::Playing(acct, chr) => {
+                // Leave the character in the world for a while in case the player comes back
+                lock(&SHARE.play_players).remove(&acct.name);
+                lock(&SHARE.controls).remove(&acct.name);
+                go_link_dead(chr);
             }
-            State::Idle(ref acct) => {
-                SHARE.play_players.lock().unwrap().remove(&acct.name);
+            State::Idle(acct) | State::Creating(acct, _) => {
+                lock(&SHARE.play_players).remove(&acct.name);
+                lock(&SHARE.controls).remove(&acct.name);
             }
         };
     }
 }
+
+// Keep a character whose player's connection dropped, so they can reconnect to it
+fn go_link_dead(chr: Character) {
+    info!("{} has gone link-dead", chr.name);
+    lock(&SHARE.link_dead).insert(chr.account.clone(), (chr, Instant::now()));
+}
+
+// Reclaim the account's link-dead character, if it has one
+fn take_link_dead(acct: &str) -> Option<Character> {
+    lock(&SHARE.link_dead).remove(acct).map(|(chr, _)| chr)
+}
+
+/// Remove characters that have been link-dead for longer than the configured grace period
+pub fn reap_link_dead() {
+    let grace = Duration::from_secs(CONFIG.game.link_dead_minutes * 60);
+    // Saving the characters can be slow, so it waits until logins can reclaim theirs again
+    let expired: Vec<Character> = {
+        let mut link_dead = lock(&SHARE.link_dead);
+        let accounts: Vec<String> = link_dead
+            .iter()
+            .filter(|&(_, &(_, since))| since.elapsed() >= grace)
+            .map(|(account, _)| account.clone())
+            .collect();
+        accounts
+            .iter()
+            .filter_map(|a| link_dead.remove(a))
+            .map(|(chr, _)| chr)
+            .collect()
+    };
+    for chr in expired {
+        info!("{} has left the world", chr.name);
+        world::leave(&chr);
+    }
+}
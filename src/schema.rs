@@ -1,11 +1,147 @@
+table! {
+    aliases (character_name, name) {
+        character_name -> Text,
+        name -> Text,
+        expansion -> Text,
+    }
+}
+
+table! {
+    account_tokens (account, purpose) {
+        account -> Text,
+        purpose -> Text,
+        token -> Text,
+        expires -> Timestamp,
+    }
+}
+
 table! {
     accounts (name) {
         name -> Text,
         email -> Nullable<Text>,
         valid -> Bool,
-        salt -> Bytea,
-        hash -> Bytea,
+        salt -> Nullable<Bytea>,
+        hash -> Nullable<Bytea>,
         created -> Timestamp,
         lastseen -> Timestamp,
+        password -> Nullable<Text>,
+        deleted -> Nullable<Timestamp>,
+        role -> Text,
+        totp_secret -> Nullable<Text>,
+        totp_enabled -> Bool,
+        totp_step -> Nullable<Int8>,
+        page_length -> Nullable<Int4>,
+    }
+}
+
+table! {
+    bans (id) {
+        id -> Int4,
+        account -> Nullable<Text>,
+        address -> Nullable<Text>,
+        reason -> Text,
+        issuer -> Text,
+        created -> Timestamp,
+        expires -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    channel_subscriptions (account, channel) {
+        account -> Text,
+        channel -> Text,
+        subscribed -> Bool,
+    }
+}
+
+table! {
+    character_drafts (account) {
+        account -> Text,
+        step -> Int4,
+        name -> Nullable<Text>,
+        description -> Nullable<Text>,
+        race -> Nullable<Text>,
+        class -> Nullable<Text>,
+        strength -> Int4,
+        dexterity -> Int4,
+        constitution -> Int4,
+        intelligence -> Int4,
+        wisdom -> Int4,
+        charisma -> Int4,
+    }
+}
+
+table! {
+    characters (name) {
+        name -> Text,
+        account -> Text,
+        created -> Timestamp,
+        lastplayed -> Timestamp,
+        description -> Text,
+        race -> Text,
+        class -> Text,
+        strength -> Int4,
+        dexterity -> Int4,
+        constitution -> Int4,
+        intelligence -> Int4,
+        wisdom -> Int4,
+        charisma -> Int4,
+        room -> Nullable<Int4>,
+        heading -> Text,
+        relative -> Bool,
+        hp -> Int4,
+        mp -> Int4,
+        prompt -> Text,
+    }
+}
+
+table! {
+    exits (room, direction) {
+        room -> Int4,
+        direction -> Text,
+        destination -> Int4,
+        closed -> Bool,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Int4,
+        account -> Text,
+        hash -> Text,
+        prefix -> Nullable<Text>,
+    }
+}
+
+table! {
+    rooms (id) {
+        id -> Int4,
+        name -> Text,
+        description -> Text,
+        spawn -> Bool,
+        safe -> Bool,
+        zone -> Text,
     }
 }
+
+joinable!(account_tokens -> accounts (account));
+joinable!(aliases -> characters (character_name));
+joinable!(bans -> accounts (account));
+joinable!(channel_subscriptions -> accounts (account));
+joinable!(character_drafts -> accounts (account));
+joinable!(characters -> accounts (account));
+joinable!(characters -> rooms (room));
+joinable!(recovery_codes -> accounts (account));
+
+allow_tables_to_appear_in_same_query!(
+    account_tokens,
+    aliases,
+    accounts,
+    bans,
+    channel_subscriptions,
+    character_drafts,
+    characters,
+    exits,
+    recovery_codes,
+    rooms,
+);
@@ -0,0 +1,490 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use super::Store;
+use error::{Error, Result};
+use account::{Account, Token};
+use ban::{Ban, NewBan};
+use character::Character;
+use chargen::Draft;
+use world::{Exit, Room};
+
+/// Keeps game data in memory, so tests can run without a database. Everything is lost when the
+/// server stops.
+pub struct MemoryStore {
+    data: Mutex<Data>,
+}
+
+#[derive(Default)]
+struct Data {
+    accounts: BTreeMap<String, Account>,
+    tokens: BTreeMap<(String, String), Token>, // Keyed by account and purpose
+    recovery_codes: BTreeMap<i32, (String, Option<String>, String)>, // Account, prefix and hash
+    characters: BTreeMap<String, Character>,
+    aliases: BTreeMap<(String, String), String>, // Expansions, keyed by character and alias name
+    subscriptions: BTreeMap<(String, String), bool>, // Keyed by account and channel
+    drafts: BTreeMap<String, Draft>,
+    bans: BTreeMap<i32, Ban>,
+    rooms: BTreeMap<i32, Room>,
+    exits: Vec<Exit>,
+    next_id: i32, // Last id handed out to a recovery code or ban
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore {
+            data: Mutex::new(Data::default()),
+        }
+    }
+
+    fn data(&self) -> Result<MutexGuard<Data>> {
+        self.data
+            .lock()
+            .map_err(|_| Error::Store(format!("Memory store poisoned by a panic")))
+    }
+}
+
+impl MemoryStore {
+    // Change some of a stored account's fields
+    fn patch_account<F: FnOnce(&mut Account)>(&self, name: &str, change: F) -> Result<()> {
+        match self.data()?.accounts.get_mut(name) {
+            Some(stored) => {
+                change(stored);
+                Ok(())
+            }
+            None => Err(Error::Store(format!("No account named {}", name))),
+        }
+    }
+}
+
+impl Data {
+    fn next_id(&mut self) -> i32 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+impl Store for MemoryStore {
+    fn migrate(&self) -> Result<()> {
+        // Players need somewhere to stand, even in a world that won't outlive the server
+        let mut data = self.data()?;
+        if data.rooms.is_empty() {
+            let room = Room {
+                id: 1,
+                name: format!("Limbo"),
+                description: format!("A featureless grey expanse, waiting to be built on."),
+                spawn: true,
+                safe: true,
+                zone: String::new(),
+            };
+            data.rooms.insert(room.id, room);
+        }
+        Ok(())
+    }
+
+    fn find_account(&self, name: &str) -> Result<Option<Account>> {
+        Ok(self.data()?.accounts.get(name).cloned())
+    }
+
+    fn find_account_nocase(&self, name: &str) -> Result<Option<Account>> {
+        let name = name.to_lowercase();
+        Ok(self.data()?
+            .accounts
+            .values()
+            .find(|a| a.name.to_lowercase() == name)
+            .cloned())
+    }
+
+    fn insert_account(&self, acct: &Account) -> Result<()> {
+        let mut data = self.data()?;
+        if data.accounts.contains_key(&acct.name) {
+            return Err(Error::Store(format!("Account {} already exists", acct.name)));
+        }
+        data.accounts.insert(acct.name.clone(), acct.clone());
+        Ok(())
+    }
+
+    fn set_password(&self, account: &str, phc: &str) -> Result<()> {
+        self.patch_account(account, |a| {
+            a.password = Some(phc.to_string());
+            a.salt = None;
+            a.hash = None;
+        })
+    }
+
+    fn set_email(&self, account: &str, email: Option<&str>, valid: bool) -> Result<()> {
+        self.patch_account(account, |a| {
+            a.email = email.map(|e| e.to_string());
+            a.valid = valid;
+        })
+    }
+
+    fn set_deleted(&self, account: &str, deleted: Option<SystemTime>) -> Result<()> {
+        self.patch_account(account, |a| a.deleted = deleted)
+    }
+
+    fn set_totp(
+        &self,
+        account: &str,
+        secret: Option<&str>,
+        enabled: bool,
+        step: Option<i64>,
+    ) -> Result<()> {
+        self.patch_account(account, |a| {
+            a.totp_secret = secret.map(|s| s.to_string());
+            a.totp_enabled = enabled;
+            a.totp_step = step;
+        })
+    }
+
+    fn set_totp_step(&self, account: &str, step: i64) -> Result<()> {
+        self.patch_account(account, |a| a.totp_step = Some(step))
+    }
+
+    fn set_page_length(&self, account: &str, lines: Option<i32>) -> Result<()> {
+        self.patch_account(account, |a| a.page_length = lines)
+    }
+
+    fn purge_accounts(&self, deleted_before: SystemTime) -> Result<usize> {
+        let mut data = self.data()?;
+        let expired: Vec<String> = data.accounts
+            .values()
+            .filter(|a| a.deleted.map_or(false, |d| d < deleted_before))
+            .map(|a| a.name.clone())
+            .collect();
+        for name in &expired {
+            data.accounts.remove(name);
+            data.tokens.retain(|&(ref account, _), _| account != name);
+            data.recovery_codes.retain(|_, &mut (ref account, _, _)| account != name);
+            let owned: Vec<String> = data.characters
+                .values()
+                .filter(|c| &c.account == name)
+                .map(|c| c.name.clone())
+                .collect();
+            data.aliases.retain(|&(ref c, _), _| !owned.contains(c));
+            data.subscriptions.retain(|&(ref account, _), _| account != name);
+            data.characters.retain(|_, c| &c.account != name);
+            data.drafts.remove(name);
+            data.bans
+                .retain(|_, b| b.account.as_ref().map_or(true, |a| a != name));
+        }
+        Ok(expired.len())
+    }
+
+    fn find_token(&self, account: &str, purpose: &str) -> Result<Option<Token>> {
+        let key = (account.to_string(), purpose.to_string());
+        Ok(self.data()?.tokens.get(&key).cloned())
+    }
+
+    fn replace_token(&self, token: &Token) -> Result<()> {
+        let key = (token.account.clone(), token.purpose.clone());
+        self.data()?.tokens.insert(key, token.clone());
+        Ok(())
+    }
+
+    fn delete_token(&self, account: &str, purpose: &str) -> Result<()> {
+        let key = (account.to_string(), purpose.to_string());
+        self.data()?.tokens.remove(&key);
+        Ok(())
+    }
+
+    fn recovery_codes(&self, account: &str, prefix: Option<&str>) -> Result<Vec<(i32, String)>> {
+        Ok(self.data()?
+            .recovery_codes
+            .iter()
+            .filter(|&(_, &(ref a, ref p, _))| a == account && p.as_ref().map(|p| &**p) == prefix)
+            .map(|(&id, &(_, _, ref hash))| (id, hash.clone()))
+            .collect())
+    }
+
+    fn delete_recovery_code(&self, id: i32) -> Result<bool> {
+        Ok(self.data()?.recovery_codes.remove(&id).is_some())
+    }
+
+    fn replace_recovery_codes(&self, account: &str, codes: &[(String, String)]) -> Result<()> {
+        let mut data = self.data()?;
+        data.recovery_codes.retain(|_, &mut (ref a, _, _)| a != account);
+        for &(ref prefix, ref hash) in codes {
+            let id = data.next_id();
+            data.recovery_codes
+                .insert(id, (account.to_string(), Some(prefix.clone()), hash.clone()));
+        }
+        Ok(())
+    }
+
+    fn find_character(&self, account: &str, name: &str) -> Result<Option<Character>> {
+        Ok(self.data()?
+            .characters
+            .get(name)
+            .filter(|c| c.account == account)
+            .cloned())
+    }
+
+    fn find_character_nocase(&self, name: &str) -> Result<Option<Character>> {
+        let name = name.to_lowercase();
+        Ok(self.data()?
+            .characters
+            .values()
+            .find(|c| c.name.to_lowercase() == name)
+            .cloned())
+    }
+
+    fn list_characters(&self, account: &str) -> Result<Vec<Character>> {
+        let mut chars: Vec<Character> = self.data()?
+            .characters
+            .values()
+            .filter(|c| c.account == account)
+            .cloned()
+            .collect();
+        chars.sort_by_key(|c| c.created);
+        Ok(chars)
+    }
+
+    fn insert_character(&self, chr: &Character) -> Result<()> {
+        let mut data = self.data()?;
+        if !data.accounts.contains_key(&chr.account) {
+            return Err(Error::Store(format!("No account named {}", chr.account)));
+        }
+        if data.characters.contains_key(&chr.name) {
+            return Err(Error::Store(format!("Character {} already exists", chr.name)));
+        }
+        data.characters.insert(chr.name.clone(), chr.clone());
+        Ok(())
+    }
+
+    fn update_character(&self, chr: &Character) -> Result<()> {
+        match self.data()?.characters.get_mut(&chr.name) {
+            Some(stored) => {
+                *stored = chr.clone();
+                Ok(())
+            }
+            None => Err(Error::Store(format!("No character named {}", chr.name))),
+        }
+    }
+
+    fn delete_character(&self, account: &str, name: &str) -> Result<bool> {
+        let mut data = self.data()?;
+        if data.characters.get(name).map_or(false, |c| c.account == account) {
+            data.characters.remove(name);
+            data.aliases.retain(|&(ref c, _), _| c != name);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn aliases(&self, character: &str) -> Result<Vec<(String, String)>> {
+        Ok(self.data()?
+            .aliases
+            .iter()
+            .filter(|&(&(ref c, _), _)| c == character)
+            .map(|(&(_, ref name), expansion)| (name.clone(), expansion.clone()))
+            .collect())
+    }
+
+    fn set_alias(&self, character: &str, name: &str, expansion: &str) -> Result<()> {
+        let key = (character.to_string(), name.to_string());
+        self.data()?.aliases.insert(key, expansion.to_string());
+        Ok(())
+    }
+
+    fn delete_alias(&self, character: &str, name: &str) -> Result<bool> {
+        let key = (character.to_string(), name.to_string());
+        Ok(self.data()?.aliases.remove(&key).is_some())
+    }
+
+    fn subscriptions(&self, account: &str) -> Result<Vec<(String, bool)>> {
+        Ok(self.data()?
+            .subscriptions
+            .iter()
+            .filter(|&(&(ref a, _), _)| a == account)
+            .map(|(&(_, ref channel), &subscribed)| (channel.clone(), subscribed))
+            .collect())
+    }
+
+    fn set_subscription(&self, account: &str, channel: &str, subscribed: bool) -> Result<()> {
+        let key = (account.to_string(), channel.to_string());
+        self.data()?.subscriptions.insert(key, subscribed);
+        Ok(())
+    }
+
+    fn find_draft(&self, account: &str) -> Result<Option<Draft>> {
+        Ok(self.data()?.drafts.get(account).cloned())
+    }
+
+    fn insert_draft(&self, draft: &Draft) -> Result<()> {
+        let mut data = self.data()?;
+        if data.drafts.contains_key(&draft.account) {
+            return Err(Error::Store(format!("{} already has a draft", draft.account)));
+        }
+        data.drafts.insert(draft.account.clone(), draft.clone());
+        Ok(())
+    }
+
+    fn update_draft(&self, draft: &Draft) -> Result<()> {
+        match self.data()?.drafts.get_mut(&draft.account) {
+            Some(stored) => {
+                *stored = draft.clone();
+                Ok(())
+            }
+            None => Err(Error::Store(format!("{} has no draft", draft.account))),
+        }
+    }
+
+    fn delete_draft(&self, account: &str) -> Result<()> {
+        self.data()?.drafts.remove(account);
+        Ok(())
+    }
+
+    fn insert_ban(&self, ban: &NewBan) -> Result<Ban> {
+        let mut data = self.data()?;
+        let ban = Ban {
+            id: data.next_id(),
+            account: ban.account.map(|a| a.to_string()),
+            address: ban.address.clone(),
+            reason: ban.reason.to_string(),
+            issuer: ban.issuer.to_string(),
+            created: ban.created,
+            expires: ban.expires,
+        };
+        data.bans.insert(ban.id, ban.clone());
+        Ok(ban)
+    }
+
+    fn active_bans(&self, now: SystemTime) -> Result<Vec<Ban>> {
+        Ok(self.data()?
+            .bans
+            .values()
+            .filter(|b| b.expires.map_or(true, |e| e > now))
+            .cloned()
+            .collect())
+    }
+
+    fn delete_ban(&self, id: i32) -> Result<bool> {
+        Ok(self.data()?.bans.remove(&id).is_some())
+    }
+
+    fn rooms(&self) -> Result<Vec<Room>> {
+        Ok(self.data()?.rooms.values().cloned().collect())
+    }
+
+    fn exits(&self) -> Result<Vec<Exit>> {
+        Ok(self.data()?.exits.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn account(name: &str) -> Account {
+        Account {
+            name: name.to_string(),
+            email: None,
+            valid: false,
+            salt: None,
+            hash: None,
+            created: SystemTime::now(),
+            lastseen: SystemTime::now(),
+            password: Some("$argon2id$old".to_string()),
+            deleted: None,
+            role: "player".to_string(),
+            totp_secret: None,
+            totp_enabled: false,
+            totp_step: None,
+            page_length: None,
+        }
+    }
+
+    fn character(account: &str, name: &str) -> Character {
+        Character {
+            name: name.to_string(),
+            account: account.to_string(),
+            created: SystemTime::now(),
+            lastplayed: SystemTime::now(),
+            description: String::new(),
+            race: "human".to_string(),
+            class: "warrior".to_string(),
+            strength: 10,
+            dexterity: 10,
+            constitution: 10,
+            intelligence: 10,
+            wisdom: 10,
+            charisma: 10,
+            room: None,
+            heading: "north".to_string(),
+            relative: false,
+            hp: 10,
+            mp: 10,
+            prompt: String::new(),
+        }
+    }
+
+    #[test]
+    fn accounts_round_trip() {
+        let store = MemoryStore::new();
+        store.insert_account(&account("Alice")).unwrap();
+        assert!(store.insert_account(&account("Alice")).is_err());
+        assert_eq!(store.find_account_nocase("alice").unwrap().unwrap().name, "Alice");
+        assert!(store.find_account("alice").unwrap().is_none());
+
+        store.set_password("Alice", "$argon2id$new").unwrap();
+        store.set_email("Alice", Some("alice@example.com"), true).unwrap();
+        store.set_page_length("Alice", Some(30)).unwrap();
+        let found = store.find_account("Alice").unwrap().unwrap();
+        assert_eq!(found.password.as_ref().unwrap(), "$argon2id$new");
+        assert_eq!(found.email.as_ref().unwrap(), "alice@example.com");
+        assert!(found.valid);
+        assert_eq!(found.page_length, Some(30));
+        assert!(store.set_password("Bob", "$argon2id$new").is_err());
+    }
+
+    #[test]
+    fn characters_round_trip() {
+        let store = MemoryStore::new();
+        assert!(store.insert_character(&character("Alice", "Ann")).is_err());
+        store.insert_account(&account("Alice")).unwrap();
+        let mut ann = character("Alice", "Ann");
+        store.insert_character(&ann).unwrap();
+        store.set_alias("Ann", "k", "kill $1").unwrap();
+
+        ann.room = Some(3);
+        store.update_character(&ann).unwrap();
+        assert_eq!(store.find_character("Alice", "Ann").unwrap().unwrap().room, Some(3));
+        assert!(store.find_character("Bob", "Ann").unwrap().is_none());
+        assert_eq!(store.list_characters("Alice").unwrap().len(), 1);
+
+        // Deleting a character takes its aliases with it
+        assert!(!store.delete_character("Bob", "Ann").unwrap());
+        assert!(store.delete_character("Alice", "Ann").unwrap());
+        assert!(store.list_characters("Alice").unwrap().is_empty());
+        assert!(store.aliases("Ann").unwrap().is_empty());
+    }
+
+    #[test]
+    fn bans_round_trip() {
+        let store = MemoryStore::new();
+        let now = SystemTime::now();
+        let ban = |account, expires| NewBan {
+            account: Some(account),
+            address: None,
+            reason: "spam",
+            issuer: "Admin",
+            created: now,
+            expires,
+        };
+        let lasting = store.insert_ban(&ban("Alice", None)).unwrap();
+        let lapsed = store.insert_ban(&ban("Bob", Some(now))).unwrap();
+        assert_ne!(lasting.id, lapsed.id);
+
+        let active = store.active_bans(now + Duration::from_secs(1)).unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].account.as_ref().unwrap(), "Alice");
+
+        assert!(store.delete_ban(lasting.id).unwrap());
+        assert!(!store.delete_ban(lasting.id).unwrap());
+        assert!(store.active_bans(now).unwrap().is_empty());
+    }
+}
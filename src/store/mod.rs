@@ -0,0 +1,127 @@
+use std::process;
+use std::time::SystemTime;
+
+use account::{Account, Token};
+use ban::{Ban, NewBan};
+use character::Character;
+use chargen::Draft;
+use config::{DatabaseConf, CONFIG};
+#[cfg(not(feature = "sqlite"))]
+use error::Error;
+use error::Result;
+use world::{Exit, Room};
+
+mod memory;
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use self::memory::MemoryStore;
+pub use self::postgres::PgStore;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStore;
+
+lazy_static! {
+    /// The storage backend all persistent game data goes through, as chosen in the configuration
+    /// file. It is first used at startup, so a backend that can't be opened stops the server
+    /// before anyone connects.
+    pub static ref STORE: Box<dyn Store> = open(&CONFIG.database).unwrap_or_else(|e| {
+        error!("Could not open the {} database. {}", CONFIG.database.backend, e);
+        process::exit(1)
+    });
+}
+
+// Open the backend named in the configuration
+fn open(conf: &DatabaseConf) -> Result<Box<dyn Store>> {
+    match conf.backend.as_str() {
+        "memory" => Ok(Box::new(MemoryStore::new())),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Ok(Box::new(SqliteStore::open(&conf.url)?)),
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => Err(Error::Store(format!("This server was built without SQLite support."))),
+        _ => Ok(Box::new(PgStore::new(&conf.url))),
+    }
+}
+
+// TODO Items and the rest of the world belong behind this trait, too, once they are persisted.
+/// Somewhere to keep accounts, characters and other game data. Errors are `Error::Store`, described
+/// for the server log rather than for players.
+pub trait Store: Send + Sync {
+    /// Create or upgrade the schema, refusing to touch one made by a newer version of the server
+    fn migrate(&self) -> Result<()>;
+
+    // Accounts
+    fn find_account(&self, name: &str) -> Result<Option<Account>>;
+    /// Find an account whose name matches in any combination of upper and lower case
+    fn find_account_nocase(&self, name: &str) -> Result<Option<Account>>;
+    fn insert_account(&self, acct: &Account) -> Result<()>;
+    // Each of these changes only its own columns, so that sessions holding older copies of the
+    // account can't undo what another has changed
+    /// Store a new password hash, dropping any legacy salt and hash
+    fn set_password(&self, account: &str, phc: &str) -> Result<()>;
+    fn set_email(&self, account: &str, email: Option<&str>, valid: bool) -> Result<()>;
+    fn set_deleted(&self, account: &str, deleted: Option<SystemTime>) -> Result<()>;
+    fn set_totp(
+        &self,
+        account: &str,
+        secret: Option<&str>,
+        enabled: bool,
+        step: Option<i64>,
+    ) -> Result<()>;
+    /// Record the time step of the last TOTP code accepted
+    fn set_totp_step(&self, account: &str, step: i64) -> Result<()>;
+    fn set_page_length(&self, account: &str, lines: Option<i32>) -> Result<()>;
+    /// Remove accounts marked deleted before the given time, and everything they own
+    fn purge_accounts(&self, deleted_before: SystemTime) -> Result<usize>;
+
+    // One-time codes mailed to players. Each account has at most one per purpose.
+    fn find_token(&self, account: &str, purpose: &str) -> Result<Option<Token>>;
+    fn replace_token(&self, token: &Token) -> Result<()>;
+    fn delete_token(&self, account: &str, purpose: &str) -> Result<()>;
+
+    // Hashed two-factor recovery codes, by id
+    /// List the ids and hashes of the account's codes that begin with the prefix, or of those
+    /// handed out before codes had prefixes
+    fn recovery_codes(&self, account: &str, prefix: Option<&str>) -> Result<Vec<(i32, String)>>;
+    fn delete_recovery_code(&self, id: i32) -> Result<bool>;
+    /// Replace all of the account's codes with new ones, given as prefixes and hashes
+    fn replace_recovery_codes(&self, account: &str, codes: &[(String, String)]) -> Result<()>;
+
+    // Characters
+    fn find_character(&self, account: &str, name: &str) -> Result<Option<Character>>;
+    /// Find a character of any account whose name matches in any combination of case
+    fn find_character_nocase(&self, name: &str) -> Result<Option<Character>>;
+    /// List the account's characters, oldest first
+    fn list_characters(&self, account: &str) -> Result<Vec<Character>>;
+    fn insert_character(&self, chr: &Character) -> Result<()>;
+    fn update_character(&self, chr: &Character) -> Result<()>;
+    fn delete_character(&self, account: &str, name: &str) -> Result<bool>;
+
+    // Aliases characters have defined, as names and expansions
+    /// List the character's aliases in order of name
+    fn aliases(&self, character: &str) -> Result<Vec<(String, String)>>;
+    /// Define an alias, replacing any the character already has by that name
+    fn set_alias(&self, character: &str, name: &str, expansion: &str) -> Result<()>;
+    fn delete_alias(&self, character: &str, name: &str) -> Result<bool>;
+
+    // Chat channels accounts have joined or left, by channel name
+    /// List the channels the account has chosen about, and whether it chose to listen to each
+    fn subscriptions(&self, account: &str) -> Result<Vec<(String, bool)>>;
+    fn set_subscription(&self, account: &str, channel: &str, subscribed: bool) -> Result<()>;
+
+    // Characters still being created. Each account has at most one.
+    fn find_draft(&self, account: &str) -> Result<Option<Draft>>;
+    fn insert_draft(&self, draft: &Draft) -> Result<()>;
+    fn update_draft(&self, draft: &Draft) -> Result<()>;
+    fn delete_draft(&self, account: &str) -> Result<()>;
+
+    // Bans
+    fn insert_ban(&self, ban: &NewBan) -> Result<Ban>;
+    /// List bans that have no expiry, or expire after the given time, oldest first
+    fn active_bans(&self, now: SystemTime) -> Result<Vec<Ban>>;
+    fn delete_ban(&self, id: i32) -> Result<bool>;
+
+    // The world map
+    fn rooms(&self) -> Result<Vec<Room>>;
+    fn exits(&self) -> Result<Vec<Exit>>;
+}
@@ -0,0 +1,423 @@
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use diesel::{delete, insert_into, update};
+use diesel_migrations::{setup_database, MigrationConnection};
+use r2d2::{Pool, PooledConnection};
+use r2d2_diesel::ConnectionManager;
+
+use std::time::SystemTime;
+
+use super::Store;
+use error::{Error, Result};
+use account::{Account, Token};
+use ban::{Ban, NewBan};
+use character::Character;
+use chargen::Draft;
+use schema::{account_tokens, accounts, aliases, bans, channel_subscriptions, character_drafts,
+             characters, exits, recovery_codes, rooms};
+use world::{Exit, Room};
+
+// Every migration in the migrations directory, built into the binary
+embed_migrations!("migrations");
+
+// Version of the newest embedded migration, found by build.rs
+include!(concat!(env!("OUT_DIR"), "/schema.rs"));
+
+sql_function! {
+    /// SQL's `lower` function, so that names can be compared without regard to case
+    fn lower(x: Text) -> Text;
+}
+
+/// Keeps game data in PostgreSQL, through a pool of connections
+pub struct PgStore {
+    pool: Pool<ConnectionManager<PgConnection>>,
+}
+
+impl PgStore {
+    pub fn new(db_url: &str) -> PgStore {
+        // Connections are made as they're needed, so the server can start, and keep running,
+        // while the database is down
+        let manager = ConnectionManager::<PgConnection>::new(db_url);
+        PgStore {
+            pool: Pool::builder().build_unchecked(manager),
+        }
+    }
+
+    fn conn(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>> {
+        self.pool
+            .get()
+            .map_err(|e| Error::Store(format!("No database connection available: {:?}", e)))
+    }
+}
+
+// Describe a diesel error for the log
+fn db_err(e: diesel::result::Error) -> Error {
+    Error::Store(format!("{:?}", e))
+}
+
+impl Store for PgStore {
+    fn migrate(&self) -> Result<()> {
+        let db_conn = self.conn()?;
+
+        setup_database(&*db_conn).map_err(db_err)?;
+        // Diesel keeps versions as text, and its latest is the greatest as text, so "9999" would
+        // come after "10000". They're compared as numbers here, as build.rs does.
+        let run = db_conn.previously_run_migration_versions().map_err(db_err)?;
+        let newer = run.iter().find(|v| v.parse::<u64>().map_or(true, |v| v > SCHEMA_VERSION));
+        if let Some(current) = newer {
+            return Err(Error::Store(format!(
+                "The database schema is at version {}, but this server only knows up to {}. \
+                 Upgrade the server before starting it.",
+                current, SCHEMA_VERSION
+            )));
+        }
+
+        let mut output = Vec::new();
+        let result = embedded_migrations::run_with_output(&*db_conn, &mut output);
+        for line in String::from_utf8_lossy(&output).lines() {
+            info!("{}", line);
+        }
+        result.map_err(|e| Error::Store(format!("{}", e)))
+    }
+
+    fn find_account(&self, name: &str) -> Result<Option<Account>> {
+        accounts::table
+            .find(name)
+            .first::<Account>(&*self.conn()?)
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn find_account_nocase(&self, name: &str) -> Result<Option<Account>> {
+        accounts::table
+            .filter(lower(accounts::name).eq(name.to_lowercase()))
+            .first::<Account>(&*self.conn()?)
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn insert_account(&self, acct: &Account) -> Result<()> {
+        insert_into(accounts::table)
+            .values(acct)
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_password(&self, account: &str, phc: &str) -> Result<()> {
+        update(accounts::table.find(account))
+            .set((
+                accounts::password.eq(phc),
+                accounts::salt.eq(None::<Vec<u8>>),
+                accounts::hash.eq(None::<Vec<u8>>),
+            ))
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_email(&self, account: &str, email: Option<&str>, valid: bool) -> Result<()> {
+        update(accounts::table.find(account))
+            .set((accounts::email.eq(email), accounts::valid.eq(valid)))
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_deleted(&self, account: &str, deleted: Option<SystemTime>) -> Result<()> {
+        update(accounts::table.find(account))
+            .set(accounts::deleted.eq(deleted))
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_totp(
+        &self,
+        account: &str,
+        secret: Option<&str>,
+        enabled: bool,
+        step: Option<i64>,
+    ) -> Result<()> {
+        update(accounts::table.find(account))
+            .set((
+                accounts::totp_secret.eq(secret),
+                accounts::totp_enabled.eq(enabled),
+                accounts::totp_step.eq(step),
+            ))
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_totp_step(&self, account: &str, step: i64) -> Result<()> {
+        update(accounts::table.find(account))
+            .set(accounts::totp_step.eq(step))
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_page_length(&self, account: &str, lines: Option<i32>) -> Result<()> {
+        update(accounts::table.find(account))
+            .set(accounts::page_length.eq(lines))
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn purge_accounts(&self, deleted_before: SystemTime) -> Result<usize> {
+        // Everything the accounts own goes with them through ON DELETE CASCADE
+        let expired = accounts::table.filter(accounts::deleted.lt(deleted_before));
+        delete(expired).execute(&*self.conn()?).map_err(db_err)
+    }
+
+    fn find_token(&self, account: &str, purpose: &str) -> Result<Option<Token>> {
+        account_tokens::table
+            .find((account, purpose))
+            .first::<Token>(&*self.conn()?)
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn replace_token(&self, token: &Token) -> Result<()> {
+        let db_conn = self.conn()?;
+        db_conn
+            .transaction(|| {
+                delete(account_tokens::table.find((&token.account, &token.purpose)))
+                    .execute(&*db_conn)?;
+                insert_into(account_tokens::table)
+                    .values(token)
+                    .execute(&*db_conn)
+            })
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn delete_token(&self, account: &str, purpose: &str) -> Result<()> {
+        delete(account_tokens::table.find((account, purpose)))
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn recovery_codes(&self, account: &str, prefix: Option<&str>) -> Result<Vec<(i32, String)>> {
+        let codes = recovery_codes::table
+            .filter(recovery_codes::account.eq(account))
+            .select((recovery_codes::id, recovery_codes::hash));
+        match prefix {
+            Some(prefix) => codes
+                .filter(recovery_codes::prefix.eq(prefix))
+                .load::<(i32, String)>(&*self.conn()?),
+            None => codes
+                .filter(recovery_codes::prefix.is_null())
+                .load::<(i32, String)>(&*self.conn()?),
+        }.map_err(db_err)
+    }
+
+    fn delete_recovery_code(&self, id: i32) -> Result<bool> {
+        delete(recovery_codes::table.find(id))
+            .execute(&*self.conn()?)
+            .map(|n| n > 0)
+            .map_err(db_err)
+    }
+
+    fn replace_recovery_codes(&self, account: &str, codes: &[(String, String)]) -> Result<()> {
+        let rows: Vec<_> = codes
+            .iter()
+            .map(|&(ref prefix, ref hash)| {
+                (
+                    recovery_codes::account.eq(account),
+                    recovery_codes::hash.eq(hash),
+                    recovery_codes::prefix.eq(Some(prefix)),
+                )
+            })
+            .collect();
+        let db_conn = self.conn()?;
+        db_conn
+            .transaction(|| {
+                delete(recovery_codes::table.filter(recovery_codes::account.eq(account)))
+                    .execute(&*db_conn)?;
+                insert_into(recovery_codes::table)
+                    .values(&rows)
+                    .execute(&*db_conn)
+            })
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn find_character(&self, account: &str, name: &str) -> Result<Option<Character>> {
+        characters::table
+            .filter(characters::name.eq(name))
+            .filter(characters::account.eq(account))
+            .first::<Character>(&*self.conn()?)
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn find_character_nocase(&self, name: &str) -> Result<Option<Character>> {
+        characters::table
+            .filter(lower(characters::name).eq(name.to_lowercase()))
+            .first::<Character>(&*self.conn()?)
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn list_characters(&self, account: &str) -> Result<Vec<Character>> {
+        characters::table
+            .filter(characters::account.eq(account))
+            .order(characters::created.asc())
+            .load::<Character>(&*self.conn()?)
+            .map_err(db_err)
+    }
+
+    fn insert_character(&self, chr: &Character) -> Result<()> {
+        insert_into(characters::table)
+            .values(chr)
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn update_character(&self, chr: &Character) -> Result<()> {
+        update(characters::table.find(&chr.name))
+            .set(chr)
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn delete_character(&self, account: &str, name: &str) -> Result<bool> {
+        let target = characters::table
+            .filter(characters::name.eq(name))
+            .filter(characters::account.eq(account));
+        delete(target)
+            .execute(&*self.conn()?)
+            .map(|n| n > 0)
+            .map_err(db_err)
+    }
+
+    fn aliases(&self, character: &str) -> Result<Vec<(String, String)>> {
+        aliases::table
+            .filter(aliases::character_name.eq(character))
+            .order(aliases::name)
+            .select((aliases::name, aliases::expansion))
+            .load::<(String, String)>(&*self.conn()?)
+            .map_err(db_err)
+    }
+
+    fn set_alias(&self, character: &str, name: &str, expansion: &str) -> Result<()> {
+        let db_conn = self.conn()?;
+        db_conn
+            .transaction(|| {
+                delete(aliases::table.find((character, name))).execute(&*db_conn)?;
+                insert_into(aliases::table)
+                    .values((
+                        aliases::character_name.eq(character),
+                        aliases::name.eq(name),
+                        aliases::expansion.eq(expansion),
+                    ))
+                    .execute(&*db_conn)
+            })
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn delete_alias(&self, character: &str, name: &str) -> Result<bool> {
+        delete(aliases::table.find((character, name)))
+            .execute(&*self.conn()?)
+            .map(|n| n > 0)
+            .map_err(db_err)
+    }
+
+    fn subscriptions(&self, account: &str) -> Result<Vec<(String, bool)>> {
+        channel_subscriptions::table
+            .filter(channel_subscriptions::account.eq(account))
+            .order(channel_subscriptions::channel)
+            .select((channel_subscriptions::channel, channel_subscriptions::subscribed))
+            .load::<(String, bool)>(&*self.conn()?)
+            .map_err(db_err)
+    }
+
+    fn set_subscription(&self, account: &str, channel: &str, subscribed: bool) -> Result<()> {
+        let db_conn = self.conn()?;
+        db_conn
+            .transaction(|| {
+                delete(channel_subscriptions::table.find((account, channel))).execute(&*db_conn)?;
+                insert_into(channel_subscriptions::table)
+                    .values((
+                        channel_subscriptions::account.eq(account),
+                        channel_subscriptions::channel.eq(channel),
+                        channel_subscriptions::subscribed.eq(subscribed),
+                    ))
+                    .execute(&*db_conn)
+            })
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn find_draft(&self, account: &str) -> Result<Option<Draft>> {
+        character_drafts::table
+            .find(account)
+            .first::<Draft>(&*self.conn()?)
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn insert_draft(&self, draft: &Draft) -> Result<()> {
+        insert_into(character_drafts::table)
+            .values(draft)
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn update_draft(&self, draft: &Draft) -> Result<()> {
+        update(character_drafts::table.find(&draft.account))
+            .set(draft)
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn delete_draft(&self, account: &str) -> Result<()> {
+        delete(character_drafts::table.find(account))
+            .execute(&*self.conn()?)
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn insert_ban(&self, ban: &NewBan) -> Result<Ban> {
+        insert_into(bans::table)
+            .values(ban)
+            .get_result::<Ban>(&*self.conn()?)
+            .map_err(db_err)
+    }
+
+    fn active_bans(&self, now: SystemTime) -> Result<Vec<Ban>> {
+        bans::table
+            .filter(bans::expires.is_null().or(bans::expires.gt(now)))
+            .order(bans::id.asc())
+            .load::<Ban>(&*self.conn()?)
+            .map_err(db_err)
+    }
+
+    fn delete_ban(&self, id: i32) -> Result<bool> {
+        delete(bans::table.find(id))
+            .execute(&*self.conn()?)
+            .map(|n| n > 0)
+            .map_err(db_err)
+    }
+
+    fn rooms(&self) -> Result<Vec<Room>> {
+        rooms::table
+            .order(rooms::id.asc())
+            .load::<Room>(&*self.conn()?)
+            .map_err(db_err)
+    }
+
+    fn exits(&self) -> Result<Vec<Exit>> {
+        exits::table.load::<Exit>(&*self.conn()?).map_err(db_err)
+    }
+}
@@ -0,0 +1,712 @@
+use rusqlite::{Connection, OptionalExtension, Row};
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::Store;
+use error::{Error, Result};
+use account::{Account, Token};
+use ban::{Ban, NewBan};
+use character::Character;
+use chargen::Draft;
+use world::{Exit, Room};
+
+// Each script brings the schema up one version from the one before it, and the database's
+// user_version records how many have been applied. Add a script whenever the schema changes.
+static MIGRATIONS: &[&str] = &[
+    include_str!("sqlite.sql"),
+    include_str!("sqlite_rooms.sql"),
+    include_str!("sqlite_closed_exits.sql"),
+    include_str!("sqlite_headings.sql"),
+    include_str!("sqlite_aliases.sql"),
+    include_str!("sqlite_prompts.sql"),
+    include_str!("sqlite_page_length.sql"),
+    include_str!("sqlite_zones.sql"),
+    include_str!("sqlite_channels.sql"),
+    include_str!("sqlite_recovery_code_prefixes.sql"),
+];
+
+static ACCOUNT_COLS: &str = "name, email, valid, salt, hash, created, lastseen, password, \
+                             deleted, role, totp_secret, totp_enabled, totp_step, page_length";
+static CHARACTER_COLS: &str = "name, account, created, lastplayed, description, race, class, \
+                               strength, dexterity, constitution, intelligence, wisdom, charisma, \
+                               room, heading, relative, hp, mp, prompt";
+static DRAFT_COLS: &str = "account, step, name, description, race, class, strength, dexterity, \
+                           constitution, intelligence, wisdom, charisma";
+static BAN_COLS: &str = "id, account, address, reason, issuer, created, expires";
+
+/// Keeps game data in a single SQLite file, for small servers that don't want to run PostgreSQL
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<SqliteStore> {
+        let conn = Connection::open(path)
+            .map_err(|e| Error::Store(format!("Failed to open {}: {}", path, e)))?;
+        // SQLite leaves foreign keys unenforced unless asked, connection by connection
+        conn.execute_batch("PRAGMA foreign_keys = ON;").map_err(|e| {
+            Error::Store(format!("Failed to enable foreign keys in {}: {}", path, e))
+        })?;
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> Result<MutexGuard<Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| Error::Store(format!("SQLite connection poisoned by a panic")))
+    }
+}
+
+// Describe a SQLite error for the log
+fn db_err(e: rusqlite::Error) -> Error {
+    Error::Store(format!("{}", e))
+}
+
+// Timestamps are stored as milliseconds since the Unix epoch
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64 * 1000 + d.subsec_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+fn account_row(row: &Row) -> rusqlite::Result<Account> {
+    Ok(Account {
+        name: row.get(0)?,
+        email: row.get(1)?,
+        valid: row.get(2)?,
+        salt: row.get(3)?,
+        hash: row.get(4)?,
+        created: from_millis(row.get(5)?),
+        lastseen: from_millis(row.get(6)?),
+        password: row.get(7)?,
+        deleted: row.get::<_, Option<i64>>(8)?.map(from_millis),
+        role: row.get(9)?,
+        totp_secret: row.get(10)?,
+        totp_enabled: row.get(11)?,
+        totp_step: row.get(12)?,
+        page_length: row.get(13)?,
+    })
+}
+
+fn character_row(row: &Row) -> rusqlite::Result<Character> {
+    Ok(Character {
+        name: row.get(0)?,
+        account: row.get(1)?,
+        created: from_millis(row.get(2)?),
+        lastplayed: from_millis(row.get(3)?),
+        description: row.get(4)?,
+        race: row.get(5)?,
+        class: row.get(6)?,
+        strength: row.get(7)?,
+        dexterity: row.get(8)?,
+        constitution: row.get(9)?,
+        intelligence: row.get(10)?,
+        wisdom: row.get(11)?,
+        charisma: row.get(12)?,
+        room: row.get(13)?,
+        heading: row.get(14)?,
+        relative: row.get(15)?,
+        hp: row.get(16)?,
+        mp: row.get(17)?,
+        prompt: row.get(18)?,
+    })
+}
+
+fn draft_row(row: &Row) -> rusqlite::Result<Draft> {
+    Ok(Draft {
+        account: row.get(0)?,
+        step: row.get(1)?,
+        name: row.get(2)?,
+        description: row.get(3)?,
+        race: row.get(4)?,
+        class: row.get(5)?,
+        strength: row.get(6)?,
+        dexterity: row.get(7)?,
+        constitution: row.get(8)?,
+        intelligence: row.get(9)?,
+        wisdom: row.get(10)?,
+        charisma: row.get(11)?,
+    })
+}
+
+fn ban_row(row: &Row) -> rusqlite::Result<Ban> {
+    Ok(Ban {
+        id: row.get(0)?,
+        account: row.get(1)?,
+        address: row.get(2)?,
+        reason: row.get(3)?,
+        issuer: row.get(4)?,
+        created: from_millis(row.get(5)?),
+        expires: row.get::<_, Option<i64>>(6)?.map(from_millis),
+    })
+}
+
+impl Store for SqliteStore {
+    fn migrate(&self) -> Result<()> {
+        let mut conn = self.conn()?;
+
+        let current: i64 = conn.query_row("PRAGMA user_version", params![], |row| row.get(0))
+            .map_err(db_err)?;
+        let current = current.max(0) as usize;
+        if current > MIGRATIONS.len() {
+            return Err(Error::Store(format!(
+                "The database schema is at version {}, but this server only knows up to {}. \
+                 Upgrade the server before starting it.",
+                current,
+                MIGRATIONS.len()
+            )));
+        }
+        for (version, script) in MIGRATIONS.iter().enumerate().skip(current) {
+            let tx = conn.transaction().map_err(db_err)?;
+            tx.execute_batch(script).map_err(db_err)?;
+            tx.execute_batch(&format!("PRAGMA user_version = {};", version + 1))
+                .map_err(db_err)?;
+            tx.commit().map_err(db_err)?;
+            info!("Migrated the database to version {}", version + 1);
+        }
+        Ok(())
+    }
+
+    fn find_account(&self, name: &str) -> Result<Option<Account>> {
+        self.conn()?
+            .query_row(
+                &format!("SELECT {} FROM accounts WHERE name = ?", ACCOUNT_COLS),
+                params![name],
+                account_row,
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn find_account_nocase(&self, name: &str) -> Result<Option<Account>> {
+        self.conn()?
+            .query_row(
+                &format!("SELECT {} FROM accounts WHERE lower(name) = ?", ACCOUNT_COLS),
+                params![name.to_lowercase()],
+                account_row,
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn insert_account(&self, acct: &Account) -> Result<()> {
+        self.conn()?
+            .execute(
+                &format!(
+                    "INSERT INTO accounts ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    ACCOUNT_COLS
+                ),
+                params![
+                    acct.name,
+                    acct.email,
+                    acct.valid,
+                    acct.salt,
+                    acct.hash,
+                    to_millis(acct.created),
+                    to_millis(acct.lastseen),
+                    acct.password,
+                    acct.deleted.map(to_millis),
+                    acct.role,
+                    acct.totp_secret,
+                    acct.totp_enabled,
+                    acct.totp_step,
+                    acct.page_length,
+                ],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_password(&self, account: &str, phc: &str) -> Result<()> {
+        self.conn()?
+            .execute(
+                "UPDATE accounts SET password = ?, salt = NULL, hash = NULL WHERE name = ?",
+                params![phc, account],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_email(&self, account: &str, email: Option<&str>, valid: bool) -> Result<()> {
+        self.conn()?
+            .execute(
+                "UPDATE accounts SET email = ?, valid = ? WHERE name = ?",
+                params![email, valid, account],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_deleted(&self, account: &str, deleted: Option<SystemTime>) -> Result<()> {
+        self.conn()?
+            .execute(
+                "UPDATE accounts SET deleted = ? WHERE name = ?",
+                params![deleted.map(to_millis), account],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_totp(
+        &self,
+        account: &str,
+        secret: Option<&str>,
+        enabled: bool,
+        step: Option<i64>,
+    ) -> Result<()> {
+        self.conn()?
+            .execute(
+                "UPDATE accounts SET totp_secret = ?, totp_enabled = ?, totp_step = ? \
+                 WHERE name = ?",
+                params![secret, enabled, step, account],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_totp_step(&self, account: &str, step: i64) -> Result<()> {
+        self.conn()?
+            .execute(
+                "UPDATE accounts SET totp_step = ? WHERE name = ?",
+                params![step, account],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn set_page_length(&self, account: &str, lines: Option<i32>) -> Result<()> {
+        self.conn()?
+            .execute(
+                "UPDATE accounts SET page_length = ? WHERE name = ?",
+                params![lines, account],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn purge_accounts(&self, deleted_before: SystemTime) -> Result<usize> {
+        // Everything the accounts own goes with them through ON DELETE CASCADE
+        self.conn()?
+            .execute(
+                "DELETE FROM accounts WHERE deleted < ?",
+                params![to_millis(deleted_before)],
+            )
+            .map_err(db_err)
+    }
+
+    fn find_token(&self, account: &str, purpose: &str) -> Result<Option<Token>> {
+        self.conn()?
+            .query_row(
+                "SELECT account, purpose, token, expires FROM account_tokens \
+                 WHERE account = ? AND purpose = ?",
+                params![account, purpose],
+                |row| {
+                    Ok(Token {
+                        account: row.get(0)?,
+                        purpose: row.get(1)?,
+                        token: row.get(2)?,
+                        expires: from_millis(row.get(3)?),
+                    })
+                },
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn replace_token(&self, token: &Token) -> Result<()> {
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO account_tokens (account, purpose, token, expires) \
+                 VALUES (?, ?, ?, ?)",
+                params![
+                    token.account,
+                    token.purpose,
+                    token.token,
+                    to_millis(token.expires),
+                ],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn delete_token(&self, account: &str, purpose: &str) -> Result<()> {
+        self.conn()?
+            .execute(
+                "DELETE FROM account_tokens WHERE account = ? AND purpose = ?",
+                params![account, purpose],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn recovery_codes(&self, account: &str, prefix: Option<&str>) -> Result<Vec<(i32, String)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare("SELECT id, hash FROM recovery_codes WHERE account = ? AND prefix IS ?")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map(params![account, prefix], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(db_err)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)
+    }
+
+    fn delete_recovery_code(&self, id: i32) -> Result<bool> {
+        self.conn()?
+            .execute("DELETE FROM recovery_codes WHERE id = ?", params![id])
+            .map(|n| n > 0)
+            .map_err(db_err)
+    }
+
+    fn replace_recovery_codes(&self, account: &str, codes: &[(String, String)]) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction().map_err(db_err)?;
+        tx.execute(
+            "DELETE FROM recovery_codes WHERE account = ?",
+            params![account],
+        ).map_err(db_err)?;
+        for &(ref prefix, ref hash) in codes {
+            tx.execute(
+                "INSERT INTO recovery_codes (account, hash, prefix) VALUES (?, ?, ?)",
+                params![account, hash, prefix],
+            ).map_err(db_err)?;
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    fn find_character(&self, account: &str, name: &str) -> Result<Option<Character>> {
+        self.conn()?
+            .query_row(
+                &format!(
+                    "SELECT {} FROM characters WHERE name = ? AND account = ?",
+                    CHARACTER_COLS
+                ),
+                params![name, account],
+                character_row,
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn find_character_nocase(&self, name: &str) -> Result<Option<Character>> {
+        self.conn()?
+            .query_row(
+                &format!(
+                    "SELECT {} FROM characters WHERE lower(name) = ?",
+                    CHARACTER_COLS
+                ),
+                params![name.to_lowercase()],
+                character_row,
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn list_characters(&self, account: &str) -> Result<Vec<Character>> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM characters WHERE account = ? ORDER BY created ASC",
+                CHARACTER_COLS
+            ))
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map(params![account], character_row)
+            .map_err(db_err)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)
+    }
+
+    fn insert_character(&self, chr: &Character) -> Result<()> {
+        self.conn()?
+            .execute(
+                &format!(
+                    "INSERT INTO characters ({}) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    CHARACTER_COLS
+                ),
+                params![
+                    chr.name,
+                    chr.account,
+                    to_millis(chr.created),
+                    to_millis(chr.lastplayed),
+                    chr.description,
+                    chr.race,
+                    chr.class,
+                    chr.strength,
+                    chr.dexterity,
+                    chr.constitution,
+                    chr.intelligence,
+                    chr.wisdom,
+                    chr.charisma,
+                    chr.room,
+                    chr.heading,
+                    chr.relative,
+                    chr.hp,
+                    chr.mp,
+                    chr.prompt,
+                ],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn update_character(&self, chr: &Character) -> Result<()> {
+        self.conn()?
+            .execute(
+                "UPDATE characters SET account = ?, created = ?, lastplayed = ?, \
+                 description = ?, race = ?, class = ?, strength = ?, dexterity = ?, \
+                 constitution = ?, intelligence = ?, wisdom = ?, charisma = ?, room = ?, \
+                 heading = ?, relative = ?, hp = ?, mp = ?, prompt = ? WHERE name = ?",
+                params![
+                    chr.account,
+                    to_millis(chr.created),
+                    to_millis(chr.lastplayed),
+                    chr.description,
+                    chr.race,
+                    chr.class,
+                    chr.strength,
+                    chr.dexterity,
+                    chr.constitution,
+                    chr.intelligence,
+                    chr.wisdom,
+                    chr.charisma,
+                    chr.room,
+                    chr.heading,
+                    chr.relative,
+                    chr.hp,
+                    chr.mp,
+                    chr.prompt,
+                    chr.name,
+                ],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn delete_character(&self, account: &str, name: &str) -> Result<bool> {
+        self.conn()?
+            .execute(
+                "DELETE FROM characters WHERE name = ? AND account = ?",
+                params![name, account],
+            )
+            .map(|n| n > 0)
+            .map_err(db_err)
+    }
+
+    fn aliases(&self, character: &str) -> Result<Vec<(String, String)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare("SELECT name, expansion FROM aliases WHERE character_name = ? ORDER BY name")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map(params![character], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(db_err)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)
+    }
+
+    fn set_alias(&self, character: &str, name: &str, expansion: &str) -> Result<()> {
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO aliases (character_name, name, expansion) VALUES (?, ?, ?)",
+                params![character, name, expansion],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn delete_alias(&self, character: &str, name: &str) -> Result<bool> {
+        self.conn()?
+            .execute(
+                "DELETE FROM aliases WHERE character_name = ? AND name = ?",
+                params![character, name],
+            )
+            .map(|n| n > 0)
+            .map_err(db_err)
+    }
+
+    fn subscriptions(&self, account: &str) -> Result<Vec<(String, bool)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT channel, subscribed FROM channel_subscriptions WHERE account = ? \
+                 ORDER BY channel",
+            )
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map(params![account], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(db_err)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)
+    }
+
+    fn set_subscription(&self, account: &str, channel: &str, subscribed: bool) -> Result<()> {
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO channel_subscriptions (account, channel, subscribed) \
+                 VALUES (?, ?, ?)",
+                params![account, channel, subscribed],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn find_draft(&self, account: &str) -> Result<Option<Draft>> {
+        self.conn()?
+            .query_row(
+                &format!(
+                    "SELECT {} FROM character_drafts WHERE account = ?",
+                    DRAFT_COLS
+                ),
+                params![account],
+                draft_row,
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    fn insert_draft(&self, draft: &Draft) -> Result<()> {
+        self.conn()?
+            .execute(
+                &format!(
+                    "INSERT INTO character_drafts ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    DRAFT_COLS
+                ),
+                params![
+                    draft.account,
+                    draft.step,
+                    draft.name,
+                    draft.description,
+                    draft.race,
+                    draft.class,
+                    draft.strength,
+                    draft.dexterity,
+                    draft.constitution,
+                    draft.intelligence,
+                    draft.wisdom,
+                    draft.charisma,
+                ],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn update_draft(&self, draft: &Draft) -> Result<()> {
+        self.conn()?
+            .execute(
+                "UPDATE character_drafts SET step = ?, name = ?, description = ?, race = ?, \
+                 class = ?, strength = ?, dexterity = ?, constitution = ?, intelligence = ?, \
+                 wisdom = ?, charisma = ? WHERE account = ?",
+                params![
+                    draft.step,
+                    draft.name,
+                    draft.description,
+                    draft.race,
+                    draft.class,
+                    draft.strength,
+                    draft.dexterity,
+                    draft.constitution,
+                    draft.intelligence,
+                    draft.wisdom,
+                    draft.charisma,
+                    draft.account,
+                ],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn delete_draft(&self, account: &str) -> Result<()> {
+        self.conn()?
+            .execute(
+                "DELETE FROM character_drafts WHERE account = ?",
+                params![account],
+            )
+            .map(|_| ())
+            .map_err(db_err)
+    }
+
+    fn insert_ban(&self, ban: &NewBan) -> Result<Ban> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO bans (account, address, reason, issuer, created, expires) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                ban.account,
+                ban.address,
+                ban.reason,
+                ban.issuer,
+                to_millis(ban.created),
+                ban.expires.map(to_millis),
+            ],
+        ).map_err(db_err)?;
+        conn.query_row(
+            &format!("SELECT {} FROM bans WHERE id = ?", BAN_COLS),
+            params![conn.last_insert_rowid()],
+            ban_row,
+        ).map_err(db_err)
+    }
+
+    fn active_bans(&self, now: SystemTime) -> Result<Vec<Ban>> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM bans WHERE expires IS NULL OR expires > ? ORDER BY id ASC",
+                BAN_COLS
+            ))
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map(params![to_millis(now)], ban_row)
+            .map_err(db_err)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)
+    }
+
+    fn delete_ban(&self, id: i32) -> Result<bool> {
+        self.conn()?
+            .execute("DELETE FROM bans WHERE id = ?", params![id])
+            .map(|n| n > 0)
+            .map_err(db_err)
+    }
+
+    fn rooms(&self) -> Result<Vec<Room>> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare("SELECT id, name, description, spawn, safe, zone FROM rooms ORDER BY id ASC")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map(params![], |row| {
+                Ok(Room {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    description: row.get(2)?,
+                    spawn: row.get(3)?,
+                    safe: row.get(4)?,
+                    zone: row.get(5)?,
+                })
+            })
+            .map_err(db_err)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)
+    }
+
+    fn exits(&self) -> Result<Vec<Exit>> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare("SELECT room, direction, destination, closed FROM exits")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map(params![], |row| {
+                Ok(Exit {
+                    room: row.get(0)?,
+                    direction: row.get(1)?,
+                    destination: row.get(2)?,
+                    closed: row.get(3)?,
+                })
+            })
+            .map_err(db_err)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err)
+    }
+}
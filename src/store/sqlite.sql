@@ -0,0 +1,79 @@
+-- Version 1 of the schema for the SQLite backend. This mirrors the first PostgreSQL migrations,
+-- except that timestamps are stored as milliseconds since the Unix epoch.
+
+CREATE TABLE IF NOT EXISTS accounts (
+  name TEXT NOT NULL PRIMARY KEY,
+  email TEXT,
+  valid BOOLEAN NOT NULL DEFAULT 0,
+  salt BLOB,
+  hash BLOB,
+  created INTEGER NOT NULL,
+  lastseen INTEGER NOT NULL,
+  password TEXT,
+  deleted INTEGER,
+  role TEXT NOT NULL DEFAULT 'player',
+  totp_secret TEXT,
+  totp_enabled BOOLEAN NOT NULL DEFAULT 0,
+  totp_step INTEGER
+);
+CREATE UNIQUE INDEX IF NOT EXISTS accounts_name_lower_idx ON accounts (lower(name));
+
+CREATE TABLE IF NOT EXISTS account_tokens (
+  account TEXT NOT NULL REFERENCES accounts (name) ON DELETE CASCADE,
+  purpose TEXT NOT NULL,
+  token TEXT NOT NULL,
+  expires INTEGER NOT NULL,
+  PRIMARY KEY (account, purpose)
+);
+
+CREATE TABLE IF NOT EXISTS recovery_codes (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  account TEXT NOT NULL REFERENCES accounts (name) ON DELETE CASCADE,
+  hash TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS recovery_codes_account_idx ON recovery_codes (account);
+
+CREATE TABLE IF NOT EXISTS characters (
+  name TEXT NOT NULL PRIMARY KEY,
+  account TEXT NOT NULL REFERENCES accounts (name) ON DELETE CASCADE,
+  created INTEGER NOT NULL,
+  lastplayed INTEGER NOT NULL,
+  description TEXT NOT NULL DEFAULT '',
+  race TEXT NOT NULL DEFAULT 'human',
+  class TEXT NOT NULL DEFAULT 'warrior',
+  strength INTEGER NOT NULL DEFAULT 8,
+  dexterity INTEGER NOT NULL DEFAULT 8,
+  constitution INTEGER NOT NULL DEFAULT 8,
+  intelligence INTEGER NOT NULL DEFAULT 8,
+  wisdom INTEGER NOT NULL DEFAULT 8,
+  charisma INTEGER NOT NULL DEFAULT 8
+);
+CREATE INDEX IF NOT EXISTS characters_account_idx ON characters (account);
+CREATE UNIQUE INDEX IF NOT EXISTS characters_name_lower_idx ON characters (lower(name));
+
+CREATE TABLE IF NOT EXISTS character_drafts (
+  account TEXT NOT NULL PRIMARY KEY REFERENCES accounts (name) ON DELETE CASCADE,
+  step INTEGER NOT NULL DEFAULT 0,
+  name TEXT,
+  description TEXT,
+  race TEXT,
+  class TEXT,
+  strength INTEGER NOT NULL DEFAULT 8,
+  dexterity INTEGER NOT NULL DEFAULT 8,
+  constitution INTEGER NOT NULL DEFAULT 8,
+  intelligence INTEGER NOT NULL DEFAULT 8,
+  wisdom INTEGER NOT NULL DEFAULT 8,
+  charisma INTEGER NOT NULL DEFAULT 8
+);
+
+CREATE TABLE IF NOT EXISTS bans (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  account TEXT REFERENCES accounts (name) ON DELETE CASCADE,
+  address TEXT,
+  reason TEXT NOT NULL,
+  issuer TEXT NOT NULL,
+  created INTEGER NOT NULL,
+  expires INTEGER,
+  CHECK (account IS NOT NULL OR address IS NOT NULL)
+);
+CREATE INDEX IF NOT EXISTS bans_account_idx ON bans (account);
@@ -0,0 +1,8 @@
+-- Version 5 of the SQLite schema, mirroring the aliases migration.
+
+CREATE TABLE aliases (
+  character_name TEXT NOT NULL REFERENCES characters (name) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  expansion TEXT NOT NULL,
+  PRIMARY KEY (character_name, name)
+);
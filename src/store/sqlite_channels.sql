@@ -0,0 +1,8 @@
+-- Version 9 of the SQLite schema, mirroring the channels migration.
+
+CREATE TABLE channel_subscriptions (
+  account TEXT NOT NULL REFERENCES accounts (name) ON DELETE CASCADE,
+  channel TEXT NOT NULL,
+  subscribed BOOLEAN NOT NULL,
+  PRIMARY KEY (account, channel)
+);
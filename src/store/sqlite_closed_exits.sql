@@ -0,0 +1,3 @@
+-- Version 3 of the SQLite schema, mirroring the closed exits migration.
+
+ALTER TABLE exits ADD COLUMN closed BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Version 4 of the SQLite schema, mirroring the headings migration.
+
+ALTER TABLE characters ADD COLUMN heading TEXT NOT NULL DEFAULT 'north';
+ALTER TABLE characters ADD COLUMN relative BOOLEAN NOT NULL DEFAULT 0;
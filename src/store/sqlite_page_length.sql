@@ -0,0 +1,3 @@
+-- Version 7 of the SQLite schema, mirroring the page length migration.
+
+ALTER TABLE accounts ADD COLUMN page_length INTEGER;
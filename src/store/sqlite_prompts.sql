@@ -0,0 +1,6 @@
+-- Version 6 of the SQLite schema, mirroring the prompts migration.
+
+ALTER TABLE characters ADD COLUMN hp INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE characters ADD COLUMN mp INTEGER NOT NULL DEFAULT 0;
+UPDATE characters SET hp = 10 + 2 * constitution, mp = intelligence + wisdom;
+ALTER TABLE characters ADD COLUMN prompt TEXT NOT NULL DEFAULT '<%h/%Hhp %m/%Mmp> ';
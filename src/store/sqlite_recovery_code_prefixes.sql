@@ -0,0 +1,3 @@
+-- Version 10 of the SQLite schema, mirroring the recovery code prefixes migration.
+
+ALTER TABLE recovery_codes ADD COLUMN prefix TEXT;
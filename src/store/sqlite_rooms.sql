@@ -0,0 +1,37 @@
+-- Version 2 of the SQLite schema, mirroring the rooms migration: the world map, and the room each
+-- character was last in.
+
+CREATE TABLE rooms (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  description TEXT NOT NULL DEFAULT '',
+  spawn BOOLEAN NOT NULL DEFAULT 0,
+  safe BOOLEAN NOT NULL DEFAULT 1
+);
+
+CREATE TABLE exits (
+  room INTEGER NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+  direction TEXT NOT NULL,
+  destination INTEGER NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+  PRIMARY KEY (room, direction)
+);
+
+ALTER TABLE characters ADD COLUMN room INTEGER REFERENCES rooms (id) ON DELETE SET NULL;
+
+INSERT INTO rooms (id, name, description, spawn, safe) VALUES
+  (1, 'Town Square', 'Cobbled streets meet around a dry fountain. The town gate lies to the north, '
+    || 'the market to the east and the temple to the south.', 1, 1),
+  (2, 'North Gate', 'A squat stone gatehouse guards the road out of town.', 0, 1),
+  (3, 'Old Forest Road', 'The road narrows as the trees close in overhead. Something moves in the '
+    || 'undergrowth.', 0, 0),
+  (4, 'Market Street', 'Stalls line both sides of the street. A shop door stands open.', 0, 1),
+  (5, 'General Store', 'Shelves of rope, lamps and dried food crowd the little shop.', 0, 1),
+  (6, 'Temple Steps', 'Wide marble steps climb towards the temple doors.', 0, 1);
+
+INSERT INTO exits (room, direction, destination) VALUES
+  (1, 'north', 2), (1, 'east', 4), (1, 'south', 6),
+  (2, 'south', 1), (2, 'north', 3),
+  (3, 'south', 2),
+  (4, 'west', 1), (4, 'in', 5),
+  (5, 'out', 4),
+  (6, 'north', 1);
@@ -0,0 +1,6 @@
+-- Version 8 of the SQLite schema, mirroring the zones migration.
+
+ALTER TABLE rooms ADD COLUMN zone TEXT NOT NULL DEFAULT '';
+
+UPDATE rooms SET zone = 'town' WHERE id IN (1, 2, 4, 5, 6);
+UPDATE rooms SET zone = 'forest' WHERE id = 3;
@@ -0,0 +1,208 @@
+use bytes::BytesMut;
+
+// Telnet commands, each sent after IAC
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250; // Start of subnegotiation
+pub const GA: u8 = 249; // Go ahead, which marks the end of a prompt
+pub const SE: u8 = 240; // End of subnegotiation
+pub const EOR: u8 = 239; // End of record, which marks the end of a prompt for clients that agree
+
+// Telnet options
+pub const OPT_EOR: u8 = 25;
+pub const OPT_NAWS: u8 = 31; // Negotiate about window size
+
+// The longest subnegotiation the server waits to see the end of. NAWS takes at most 13 bytes,
+// and the server offers nothing longer.
+const MAX_SUBNEGOTIATION: usize = 64;
+
+/// Sent as a session opens, offering to mark the end of each prompt with EOR rather than GA, and
+/// asking the client to report its screen size
+pub static OFFER: &[u8] = &[IAC, WILL, OPT_EOR, IAC, DO, OPT_NAWS];
+
+/// The telnet options a client has agreed to
+#[derive(Debug, Default)]
+pub struct Options {
+    pub eor: bool,             // Whether prompts end with EOR rather than GA
+    pub height: Option<usize>, // Lines on the client's screen, if it has said
+    discarding: bool,          // Whether an overlong subnegotiation is being thrown away
+}
+
+impl Options {
+    /// The bytes that follow a prompt, so clients can tell it from other output
+    pub fn end_of_prompt(&self) -> &'static [u8] {
+        if self.eor {
+            &[IAC, EOR]
+        } else {
+            &[IAC, GA]
+        }
+    }
+
+    /// Move what the client sent from `raw` to `text`, taking out telnet commands and noting any
+    /// options they settle. A command cut off at the end of `raw` waits there for the rest of it.
+    pub fn filter(&mut self, raw: &mut BytesMut, text: &mut BytesMut) {
+        let mut i = 0;
+        while i < raw.len() {
+            if self.discarding {
+                match sub_end(&raw[i..]) {
+                    Ok(end) => {
+                        self.discarding = false;
+                        i += end + 2;
+                        continue;
+                    }
+                    Err(scanned) => {
+                        i += scanned;
+                        break;
+                    }
+                }
+            }
+            if raw[i] != IAC {
+                let end = raw[i..].iter().position(|&b| b == IAC).map_or(raw.len(), |n| i + n);
+                text.extend_from_slice(&raw[i..end]);
+                i = end;
+                continue;
+            }
+            let len = match raw.get(i + 1) {
+                None => break,
+                // A doubled IAC is a literal byte 255
+                Some(&IAC) => {
+                    text.extend_from_slice(&[IAC]);
+                    2
+                }
+                Some(&verb) if verb >= WILL => match raw.get(i + 2) {
+                    Some(&opt) => {
+                        self.negotiate(verb, opt);
+                        3
+                    }
+                    None => break,
+                },
+                Some(&SB) => match sub_end(&raw[i + 2..]) {
+                    Ok(end) => {
+                        self.subnegotiate(&raw[i + 2..i + 2 + end]);
+                        end + 4
+                    }
+                    // A client that never ends it mustn't leave the session waiting forever
+                    Err(_) if raw.len() - i > MAX_SUBNEGOTIATION => {
+                        warn!("Discarding a subnegotiation over {} bytes", MAX_SUBNEGOTIATION);
+                        self.discarding = true;
+                        2
+                    }
+                    Err(_) => break,
+                },
+                Some(_) => 2,
+            };
+            i += len;
+        }
+        raw.split_to(i);
+    }
+
+    // Note what the client said of an option. Options the server never offered are ignored.
+    fn negotiate(&mut self, verb: u8, opt: u8) {
+        match (verb, opt) {
+            (DO, OPT_EOR) => self.eor = true,
+            (DONT, OPT_EOR) => self.eor = false,
+            (WONT, OPT_NAWS) => self.height = None,
+            _ => {}
+        }
+    }
+
+    // Take in what the client reported about an option, given as the option followed by its data
+    fn subnegotiate(&mut self, sub: &[u8]) {
+        // Any 255 in the data is doubled, so it can't be mistaken for IAC
+        let mut data = Vec::new();
+        let mut bytes = sub.iter().skip(1);
+        while let Some(&b) = bytes.next() {
+            if b == IAC {
+                bytes.next();
+            }
+            data.push(b);
+        }
+        // NAWS gives the width and then the height, each as two bytes. Zero means unknown.
+        if sub.first() == Some(&OPT_NAWS) && data.len() == 4 {
+            let height = (data[2] as usize) << 8 | data[3] as usize;
+            self.height = if height > 0 { Some(height) } else { None };
+        }
+    }
+}
+
+// Find the IAC SE that ends subnegotiation data, passing over doubled IACs within it. If it
+// hasn't arrived, says how many bytes were looked at, leaving out an IAC whose meaning depends
+// on what comes next.
+fn sub_end(data: &[u8]) -> Result<usize, usize> {
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] != IAC {
+            i += 1;
+            continue;
+        }
+        match data.get(i + 1) {
+            Some(&SE) => return Ok(i),
+            Some(_) => i += 2,
+            None => break,
+        }
+    }
+    Err(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Filter the bytes in the chunks given, as if each arrived in a read of its own
+    fn filter(options: &mut Options, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut raw = BytesMut::new();
+        let mut text = BytesMut::new();
+        for chunk in chunks {
+            raw.extend_from_slice(chunk);
+            options.filter(&mut raw, &mut text);
+        }
+        text.to_vec()
+    }
+
+    #[test]
+    fn filter_passes_text_and_unescapes_iac() {
+        let mut options = Options::default();
+        assert_eq!(filter(&mut options, &[b"look\r\n"]), b"look\r\n");
+        assert_eq!(filter(&mut options, &[&[b'a', IAC, IAC, b'b']]), &[b'a', IAC, b'b']);
+    }
+
+    #[test]
+    fn filter_notes_negotiated_options() {
+        let mut options = Options::default();
+        let text = filter(&mut options, &[&[b'h', IAC, DO], &[OPT_EOR, b'i']]);
+        assert_eq!(text, b"hi");
+        assert!(options.eor);
+    }
+
+    #[test]
+    fn filter_reads_window_size_split_across_reads() {
+        let mut options = Options::default();
+        let text = filter(
+            &mut options,
+            &[&[IAC, SB, OPT_NAWS, 0, 80], &[0, 24, IAC, SE, b'x']],
+        );
+        assert_eq!(text, b"x");
+        assert_eq!(options.height, Some(24));
+    }
+
+    #[test]
+    fn filter_reads_past_an_escaped_iac_se_in_subnegotiation() {
+        let mut options = Options::default();
+        // A height of 65520 is sent as the bytes 255 and 240, the first of them doubled
+        let sub = [IAC, SB, OPT_NAWS, 0, 80, IAC, IAC, SE, IAC, SE, b'x'];
+        assert_eq!(filter(&mut options, &[&sub]), b"x");
+        assert_eq!(options.height, Some(65520));
+    }
+
+    #[test]
+    fn filter_discards_a_subnegotiation_that_never_ends() {
+        let mut options = Options::default();
+        let junk = [0u8; MAX_SUBNEGOTIATION];
+        let text = filter(&mut options, &[&[IAC, SB, OPT_NAWS], &junk, &junk, b"look"]);
+        assert!(text.is_empty());
+        assert_eq!(filter(&mut options, &[&[IAC, SE], b"look"]), b"look");
+    }
+}
@@ -0,0 +1,110 @@
+use base32::{self, Alphabet};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, Rng};
+use sha1::Sha1;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Length of each time step, in seconds
+const PERIOD: u64 = 30;
+// Number of digits in each code
+const DIGITS: u32 = 6;
+// Number of steps either side of the current one to accept, to allow for clock drift
+const DRIFT: i64 = 1;
+
+static ALPHABET: Alphabet = Alphabet::RFC4648 { padding: false };
+
+/// Generate a new random secret, encoded in base32 as authenticator apps expect
+pub fn generate_secret() -> String {
+    let mut key = [0u8; 20];
+    thread_rng().fill(&mut key[..]);
+    base32::encode(ALPHABET, &key)
+}
+
+/// An otpauth URI that authenticator apps can import, usually by scanning it as a QR code
+pub fn uri(account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/RedMUD:{}?secret={}&issuer=RedMUD&algorithm=SHA1&digits={}&period={}",
+        percent_encode(account),
+        secret,
+        DIGITS,
+        PERIOD
+    )
+}
+
+/// Check a code against the secret, as described in RFC 6238. On success, returns the time step
+/// the code belongs to so the caller can refuse to accept the same code twice.
+pub fn verify(secret: &str, code: &str, last_step: Option<i64>) -> Option<i64> {
+    let key = base32::decode(ALPHABET, secret)?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+    let now = (SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() / PERIOD) as i64;
+
+    (now - DRIFT..now + DRIFT + 1)
+        .filter(|&step| last_step.map_or(true, |last| step > last))
+        .find(|&step| hotp(&key, step as u64) == code)
+}
+
+// Compute the HOTP value for a counter, as described in RFC 4226
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation: the low nibble of the last byte picks which four bytes to use
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = ((digest[offset] as u32 & 0x7f) << 24)
+        | ((digest[offset + 1] as u32) << 16)
+        | ((digest[offset + 2] as u32) << 8)
+        | (digest[offset + 3] as u32);
+    value % 10u32.pow(DIGITS)
+}
+
+// Escape anything in an account name that isn't safe to put in a URI path
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SHA-1 test vectors of RFC 6238, appendix B, cut to six digits
+    #[test]
+    fn hotp_matches_rfc_6238() {
+        let key = b"12345678901234567890";
+        let vectors = [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ];
+        for &(time, code) in &vectors {
+            assert_eq!(hotp(key, time / PERIOD), code, "at time {}", time);
+        }
+    }
+
+    #[test]
+    fn verify_accepts_only_the_current_code() {
+        let secret = generate_secret();
+        let key = base32::decode(ALPHABET, &secret).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() / PERIOD;
+        let code = format!("{:06}", hotp(&key, now));
+        let step = verify(&secret, &code, None).unwrap();
+        assert!(verify(&secret, &code, Some(step)).is_none());
+        assert!(verify(&secret, &format!("+{}", &code[1..]), None).is_none());
+        assert!(verify(&secret, &format!("0{}", code), None).is_none());
+    }
+}
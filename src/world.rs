@@ -0,0 +1,550 @@
+use bytes::Bytes;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::process;
+
+use alias;
+use channel;
+use character::Character;
+use error::{Error, Result};
+use shared::{lock, SHARE};
+use store::STORE;
+
+lazy_static! {
+    /// The map of rooms and the exits between them, loaded from storage when the server starts.
+    /// A world without a spawn room has nowhere to put players, so the server stops instead.
+    pub static ref WORLD: World = World::load().unwrap_or_else(|e| {
+        error!("Could not load the world. {}", e);
+        process::exit(1)
+    });
+}
+
+/// How many rooms out from the character the minimap reaches, unless the player asks otherwise
+pub const MAP_RADIUS: i32 = 2;
+/// The farthest a player may ask the minimap to reach
+pub const MAX_MAP_RADIUS: i32 = 5;
+
+/// The ways out of a room, in the order they're listed to players
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    North,
+    Northeast,
+    East,
+    Southeast,
+    South,
+    Southwest,
+    West,
+    Northwest,
+    Up,
+    Down,
+    In,
+    Out,
+}
+
+// The compass directions, clockwise from north. Headings and turns count steps around it.
+const COMPASS: [Direction; 8] = [
+    Direction::North,
+    Direction::Northeast,
+    Direction::East,
+    Direction::Southeast,
+    Direction::South,
+    Direction::Southwest,
+    Direction::West,
+    Direction::Northwest,
+];
+
+// Where each compass step leads on the minimap, as columns right and rows down
+const OFFSETS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+// Each compass step clockwise from the way a character faces, as it's described to them
+const BEARINGS: [&str; 8] = [
+    "ahead",
+    "ahead right",
+    "right",
+    "behind right",
+    "behind",
+    "behind left",
+    "left",
+    "ahead left",
+];
+
+impl Direction {
+    /// Parse a direction's full name, as it's stored with an exit
+    pub fn parse(name: &str) -> Option<Direction> {
+        match name {
+            "north" => Some(Direction::North),
+            "northeast" => Some(Direction::Northeast),
+            "east" => Some(Direction::East),
+            "southeast" => Some(Direction::Southeast),
+            "south" => Some(Direction::South),
+            "southwest" => Some(Direction::Southwest),
+            "west" => Some(Direction::West),
+            "northwest" => Some(Direction::Northwest),
+            "up" => Some(Direction::Up),
+            "down" => Some(Direction::Down),
+            "in" => Some(Direction::In),
+            "out" => Some(Direction::Out),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Direction::North => "north",
+            Direction::Northeast => "northeast",
+            Direction::East => "east",
+            Direction::Southeast => "southeast",
+            Direction::South => "south",
+            Direction::Southwest => "southwest",
+            Direction::West => "west",
+            Direction::Northwest => "northwest",
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+
+    /// The direction that leads back the way this one came
+    pub fn opposite(&self) -> Direction {
+        match *self {
+            Direction::North => Direction::South,
+            Direction::Northeast => Direction::Southwest,
+            Direction::East => Direction::West,
+            Direction::Southeast => Direction::Northwest,
+            Direction::South => Direction::North,
+            Direction::Southwest => Direction::Northeast,
+            Direction::West => Direction::East,
+            Direction::Northwest => Direction::Southeast,
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::In => Direction::Out,
+            Direction::Out => Direction::In,
+        }
+    }
+
+    // Steps clockwise from north, for the compass directions
+    fn bearing(&self) -> Option<usize> {
+        COMPASS.iter().position(|d| d == self)
+    }
+
+    /// The direction to the given side of someone facing this way. Only compass directions can be
+    /// turned from, so the others are returned unchanged.
+    pub fn turn(&self, side: Side) -> Direction {
+        match self.bearing() {
+            Some(b) => COMPASS[(b + side.steps()) % 8],
+            None => *self,
+        }
+    }
+
+    /// Where this direction lies for someone facing the given heading, as in "ahead left"
+    pub fn relative_to(&self, heading: Direction) -> Option<&'static str> {
+        match (self.bearing(), heading.bearing()) {
+            (Some(b), Some(h)) => Some(BEARINGS[(b + 8 - h) % 8]),
+            _ => None,
+        }
+    }
+
+    // How someone leaving this way is described, as in "Bob leaves to the north"
+    fn toward(&self) -> String {
+        match *self {
+            Direction::Up => format!("upwards"),
+            Direction::Down => format!("downwards"),
+            Direction::In => format!("inside"),
+            Direction::Out => format!("outside"),
+            _ => format!("to the {}", self.name()),
+        }
+    }
+
+    // How someone arriving from this way is described, as in "Bob arrives from the north"
+    fn from(&self) -> String {
+        match *self {
+            Direction::Up => format!("from above"),
+            Direction::Down => format!("from below"),
+            Direction::In => format!("from inside"),
+            Direction::Out => format!("from outside"),
+            _ => format!("from the {}", self.name()),
+        }
+    }
+}
+
+/// Ways to go relative to where a character is facing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Forward,
+    Right,
+    Back,
+    Left,
+}
+
+impl Side {
+    // Steps clockwise around the compass from the way the character faces
+    fn steps(&self) -> usize {
+        match *self {
+            Side::Forward => 0,
+            Side::Right => 2,
+            Side::Back => 4,
+            Side::Left => 6,
+        }
+    }
+}
+
+/// A place in the world
+#[derive(Queryable, Debug, Clone)]
+pub struct Room {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub spawn: bool,  // New and lost characters are placed in spawn rooms
+    pub safe: bool,   // No fighting is allowed in safe rooms
+    pub zone: String, // The area it belongs to, which a shout carries across
+}
+
+/// A way from one room to another, as it's stored
+#[derive(Queryable, Debug, Clone)]
+pub struct Exit {
+    pub room: i32,
+    pub direction: String,
+    pub destination: i32,
+    pub closed: bool, // Nobody may pass through a closed exit
+}
+
+/// Every room in the world, and where each of their exits lead
+pub struct World {
+    rooms: BTreeMap<i32, Room>,
+    exits: BTreeMap<i32, BTreeMap<Direction, Exit>>, // Exits by direction, keyed by room
+    spawn: i32, // Where characters go when they have nowhere else to be
+}
+
+impl World {
+    // Build the world graph from the rooms and exits in storage
+    fn load() -> Result<World> {
+        let rooms: BTreeMap<i32, Room> = STORE.rooms()?.into_iter().map(|r| (r.id, r)).collect();
+        let spawn = rooms
+            .values()
+            .find(|r| r.spawn)
+            .map(|r| r.id)
+            .ok_or_else(|| Error::Store(format!("There are no spawn rooms.")))?;
+
+        let mut exits: BTreeMap<i32, BTreeMap<Direction, Exit>> = BTreeMap::new();
+        for exit in STORE.exits()? {
+            match Direction::parse(&exit.direction) {
+                Some(dir) if rooms.contains_key(&exit.destination) => {
+                    exits.entry(exit.room).or_default().insert(dir, exit);
+                }
+                Some(_) => warn!("Ignoring {:?}, which leads nowhere", exit),
+                None => warn!("Ignoring {:?}, which has an unknown direction", exit),
+            }
+        }
+
+        info!("Loaded {} rooms", rooms.len());
+        Ok(World {
+            rooms,
+            exits,
+            spawn,
+        })
+    }
+
+    pub fn room(&self, id: i32) -> Option<&Room> {
+        self.rooms.get(&id)
+    }
+
+    /// The room's exit in the given direction, if it has one
+    pub fn exit(&self, room: i32, dir: Direction) -> Option<&Exit> {
+        self.exits.get(&room).and_then(|e| e.get(&dir))
+    }
+
+    /// The directions a room has exits in, in order
+    pub fn exits(&self, room: i32) -> Vec<Direction> {
+        self.exits
+            .get(&room)
+            .map(|e| e.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The room a character should be in: the one they were last in if it still exists, or else
+    /// a spawn room
+    pub fn place(&self, room: Option<i32>) -> i32 {
+        match room {
+            Some(id) if self.rooms.contains_key(&id) => id,
+            _ => self.spawn,
+        }
+    }
+
+    /// Describe a room to a player standing in it. Given a heading, exits are also described
+    /// relative to it.
+    pub fn describe(&self, id: i32, heading: Option<Direction>) -> String {
+        let room = match self.room(id) {
+            Some(room) => room,
+            None => return format!("You are nowhere at all.\n"),
+        };
+        let exits: Vec<String> = self.exits
+            .get(&id)
+            .map(|e| {
+                e.iter()
+                    .map(|(dir, exit)| {
+                        let side = heading.and_then(|h| dir.relative_to(h));
+                        match (side, exit.closed) {
+                            (Some(side), true) => format!("{} ({}, closed)", dir.name(), side),
+                            (Some(side), false) => format!("{} ({})", dir.name(), side),
+                            (None, true) => format!("{} (closed)", dir.name()),
+                            (None, false) => dir.name().to_string(),
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        format!(
+            "{}\n  {}\nExits: {}\n",
+            room.name,
+            room.description,
+            if exits.is_empty() {
+                "none".to_string()
+            } else {
+                exits.join(", ")
+            }
+        )
+    }
+}
+
+/// Put a character into the world, so that others in the room can see them come and go
+pub fn enter(chr: &Character) {
+    let room = WORLD.place(chr.room);
+    let returning = lock(&SHARE.locations)
+        .insert(chr.account.clone(), (chr.name.clone(), room))
+        .is_some();
+    if !returning {
+        tell_room(room, &chr.account, &format!("{} has entered the world.\n", chr.name));
+    }
+}
+
+/// Take a character out of the world, saving them so they come back where they left off
+pub fn leave(chr: &Character) {
+    alias::forget(&chr.name);
+    channel::forget(&chr.account);
+    lock(&SHARE.replies).remove(&chr.name);
+    if let Some((_, room)) = lock(&SHARE.locations).remove(&chr.account) {
+        tell_room(room, &chr.account, &format!("{} has left the world.\n", chr.name));
+    }
+    if let Err(e) = STORE.update_character(chr) {
+        error!("Database error saving {} as they left the world: {}", chr.name, e);
+    }
+}
+
+/// The compass direction a character faces
+pub fn facing(chr: &Character) -> Direction {
+    match Direction::parse(&chr.heading) {
+        Some(dir) if dir.bearing().is_some() => dir,
+        _ => Direction::North,
+    }
+}
+
+/// Describe the character's surroundings, including who else is there
+pub fn look(chr: &Character) -> String {
+    let room = WORLD.place(chr.room);
+    let view = if chr.relative {
+        let heading = facing(chr);
+        format!("{}You are facing {}.\n", WORLD.describe(room, Some(heading)), heading.name())
+    } else {
+        WORLD.describe(room, None)
+    };
+    let mut others: Vec<String> = lock(&SHARE.locations)
+        .iter()
+        .filter(|&(acct, &(_, r))| r == room && acct != &chr.account)
+        .map(|(_, &(ref name, _))| name.clone())
+        .collect();
+    others.sort();
+    if others.is_empty() {
+        view
+    } else {
+        format!("{}Also here: {}\n", view, others.join(", "))
+    }
+}
+
+/// Draw the rooms around the character that can be reached by compass exits, with north at the
+/// top, or with the way they face at the top if they view the world relative to it
+pub fn map(chr: &Character, radius: i32) -> String {
+    let here = WORLD.place(chr.room);
+    let turn = if chr.relative {
+        facing(chr).bearing().unwrap_or(0)
+    } else {
+        0
+    };
+    // Where the exit in a direction leads on the map, turned to the character's view
+    let offset = |dir: &Direction| dir.bearing().map(|b| OFFSETS[(b + 8 - turn) % 8]);
+
+    // Lay rooms out breadth first, so nearer rooms win when two would share a spot
+    let mut placed: BTreeMap<i32, (i32, i32)> = BTreeMap::new();
+    let mut taken: BTreeMap<(i32, i32), i32> = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    placed.insert(here, (0, 0));
+    taken.insert((0, 0), here);
+    queue.push_back((here, 0, 0));
+    while let Some((room, x, y)) = queue.pop_front() {
+        for dir in WORLD.exits(room) {
+            let (dx, dy) = match offset(&dir) {
+                Some(o) => o,
+                None => continue,
+            };
+            let (nx, ny) = (x + dx, y + dy);
+            let dest = match WORLD.exit(room, dir) {
+                Some(exit) => exit.destination,
+                None => continue,
+            };
+            if nx.abs() > radius || ny.abs() > radius
+                || placed.contains_key(&dest)
+                || taken.contains_key(&(nx, ny))
+            {
+                continue;
+            }
+            placed.insert(dest, (nx, ny));
+            taken.insert((nx, ny), dest);
+            queue.push_back((dest, nx, ny));
+        }
+    }
+
+    // Rooms sit two cells apart, with the passages between them drawn in the cells between
+    let size = (radius * 4 + 1) as usize;
+    let cell = |v: i32| (v + radius * 2) as usize;
+    let mut grid = vec![vec![' '; size]; size];
+    for (&room, &(x, y)) in &placed {
+        for dir in WORLD.exits(room) {
+            let (dx, dy) = match offset(&dir) {
+                Some(o) => o,
+                None => continue,
+            };
+            let dest = WORLD.exit(room, dir).map(|e| e.destination);
+            if dest.and_then(|d| placed.get(&d)) != Some(&(x + dx, y + dy)) {
+                continue;
+            }
+            let c = match (dx, dy) {
+                (0, _) => '|',
+                (_, 0) => '-',
+                _ if dx == dy => '\\',
+                _ => '/',
+            };
+            let spot = &mut grid[cell(y * 2 + dy)][cell(x * 2 + dx)];
+            *spot = if *spot == ' ' || *spot == c { c } else { 'X' };
+        }
+        grid[cell(y * 2)][cell(x * 2)] = if room == here { '@' } else { '#' };
+    }
+
+    let rows: Vec<String> = grid
+        .iter()
+        .map(|row| format!("  {}", row.iter().collect::<String>().trim_end()))
+        .collect();
+    let top = if chr.relative {
+        format!("You are facing {}, at the top.", facing(chr).name())
+    } else {
+        format!("North is at the top.")
+    };
+    format!("{}\n{}\n", top, rows.join("\n"))
+}
+
+/// Move a character through the exit in the given direction, returning what they see
+pub fn travel(chr: &mut Character, dir: Direction) -> String {
+    let here = WORLD.place(chr.room);
+    let there = match WORLD.exit(here, dir) {
+        Some(exit) if exit.closed => return format!("The way {} is closed.\n", dir.name()),
+        Some(exit) => exit.destination,
+        None => return format!("You can't go {} from here.\n", dir.name()),
+    };
+
+    tell_room(here, &chr.account, &format!("{} leaves {}.\n", chr.name, dir.toward()));
+    chr.room = Some(there);
+    // Walking a compass direction turns the character to face it
+    if dir.bearing().is_some() {
+        chr.heading = dir.name().to_string();
+    }
+    lock(&SHARE.locations).insert(chr.account.clone(), (chr.name.clone(), there));
+    tell_room(
+        there,
+        &chr.account,
+        &format!("{} arrives {}.\n", chr.name, dir.opposite().from()),
+    );
+
+    // The new room and heading are saved when the character leaves the world, not at every step
+    look(chr)
+}
+
+/// Choose whether the character sees exits and maps relative to their heading
+pub fn view(chr: &mut Character, relative: bool) -> String {
+    chr.relative = relative;
+    if let Err(e) = STORE.update_character(chr) {
+        error!("Database error saving the view of {}: {}", chr.name, e);
+    }
+    if relative {
+        format!("Exits and maps are now shown by the way you face.\n")
+    } else {
+        format!("Exits and maps are now shown by the compass.\n")
+    }
+}
+
+/// Send a message to everyone in a room, except the account it's about
+pub fn tell_room(room: i32, except: &str, msg: &str) {
+    tell_onlookers(room, &[except], msg)
+}
+
+/// Send a message to everyone in a room but the accounts it's about, as when one character does
+/// something to another
+pub fn tell_onlookers(room: i32, except: &[&str], msg: &str) {
+    // Gather the recipients first, so no lock is held while sending
+    let txs: Vec<_> = {
+        let locations = lock(&SHARE.locations);
+        let players = lock(&SHARE.play_players);
+        locations
+            .iter()
+            .filter(|&(acct, &(_, r))| r == room && !except.contains(&acct.as_str()))
+            .filter_map(|(acct, _)| players.get(acct).cloned())
+            .collect()
+    };
+    for tx in txs {
+        let _ = tx.unbounded_send(Bytes::from(msg));
+    }
+}
+
+/// Find a character in the world by name, whatever its case. Gives their account, their name as
+/// it's spelled, and the room they're in.
+pub fn find_player(name: &str) -> Option<(String, String, i32)> {
+    let name = name.to_lowercase();
+    lock(&SHARE.locations)
+        .iter()
+        .find(|&(_, &(ref n, _))| n.to_lowercase() == name)
+        .map(|(acct, &(ref n, room))| (acct.clone(), n.clone(), room))
+}
+
+/// Send a message to the player of an account. Returns false if nobody is there to hear it, as
+/// when the character is link-dead.
+pub fn tell_player(account: &str, msg: &str) -> bool {
+    let tx = lock(&SHARE.play_players).get(account).cloned();
+    match tx {
+        Some(tx) => tx.unbounded_send(Bytes::from(msg)).is_ok(),
+        None => false,
+    }
+}
+
+/// Send a message to everyone in the rooms of a zone, except the given account
+pub fn tell_zone(zone: &str, except: &str, msg: &str) {
+    let in_zone = |room: i32| WORLD.room(room).map_or(false, |r| r.zone == zone);
+    let txs: Vec<_> = {
+        let locations = lock(&SHARE.locations);
+        let players = lock(&SHARE.play_players);
+        locations
+            .iter()
+            .filter(|&(acct, &(_, r))| acct != except && in_zone(r))
+            .filter_map(|(acct, _)| players.get(acct).cloned())
+            .collect()
+    };
+    for tx in txs {
+        let _ = tx.unbounded_send(Bytes::from(msg));
+    }
+}